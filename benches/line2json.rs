@@ -0,0 +1,85 @@
+//!
+//! line2json on a generated list, `cargo bench --bench line2json -- [lines] [legacy]`
+//!
+//! half of the files sit in one huge folder and the rest spread over a thousand small ones, the
+//! lines alternate between the two so every folder is looked up again and again through the
+//! index, time should grow linearly with the lines, the whole tree is held in memory
+//!
+use std::io::Write;
+use std::path::Path;
+use std::time::Instant;
+
+const DEFAULT_LINES: usize = 1_000_000;
+
+/// peak resident memory of this process in KiB, linux only
+fn peak_rss_kib() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn write_list(path: &Path, lines: usize) -> std::io::Result<()> {
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+    for i in 0..lines {
+        let folder = if i % 2 == 0 {
+            "big".to_owned()
+        } else {
+            format!("small/{:04}", i % 1000)
+        };
+        writeln!(
+            writer,
+            "115://file_{:08}.mkv|{}|{:040X}|{:040X}|root|{}",
+            i,
+            i * 1024,
+            i,
+            i + 1,
+            folder.replace('/', "|")
+        )?;
+    }
+    writer.flush()
+}
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let lines = args
+        .iter()
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_LINES);
+    // the bot keeps loose files and empty folders unless told otherwise
+    let preserve = !args.iter().any(|arg| arg == "legacy");
+    let dir = std::env::temp_dir().join(format!("line2json_bench_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let input = dir.join("list.txt");
+    let output = dir.join("list.json");
+
+    write_list(&input, lines)?;
+    let input_size = std::fs::metadata(&input)?.len();
+    let before = peak_rss_kib();
+
+    let started = Instant::now();
+    tokio::runtime::Runtime::new()?
+        .block_on(rs115_bot::bench::line2json(&input, &output, preserve))?;
+    let elapsed = started.elapsed();
+
+    println!("lines:       {}", lines);
+    println!(
+        "mode:        {}",
+        if preserve { "preserve" } else { "legacy" }
+    );
+    println!("input:       {} KiB", input_size / 1024);
+    println!(
+        "output:      {} KiB",
+        std::fs::metadata(&output)?.len() / 1024
+    );
+    println!("time:        {:.2?}", elapsed);
+    if let (Some(before), Some(after)) = (before, peak_rss_kib()) {
+        println!("peak memory: {} KiB ({} KiB before)", after, before);
+        println!(
+            "per line:    {} bytes",
+            (after - before) * 1024 / lines as u64
+        );
+    }
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -1,8 +1,12 @@
 use crate::callback_handlers::callback_handler;
 use crate::commands::Command;
-use crate::global::{Bot, DEBUG_CC_ID, ROOT_FOLDER};
+use crate::global::{
+    Bot, DEBUG_CC_ID, LEGACY_ROUND_TRIP, LOCAL_API_MAX_FILE_SIZE, LOCAL_BOT_API, MAX_FILE_SIZE,
+    PUBLIC_API_MAX_FILE_SIZE, ROOT_FOLDER, TORRENT_CACHE_FOLDER,
+};
 use crate::inline_handlers::inline_query_handler;
 use crate::message_handlers::message_handler;
+use crate::parsers::{parse_size, to_iec};
 use crate::search::Librarian;
 use anyhow::Result;
 use std::path::Path;
@@ -26,16 +30,18 @@ async fn set_up_commands(bot: &Bot) -> Result<()> {
         .collect();
 
     let mut smc = bot.set_my_commands(list);
-    let mut payload = smc.payload_mut();
+    let payload = smc.payload_mut();
     payload.scope = Some(BotCommandScope::AllPrivateChats);
     smc.await?;
     Ok(())
 }
 
 async fn init_caches() -> Result<()> {
-    let path = Path::new(ROOT_FOLDER);
-    if !path.exists() {
-        create_dir_all(path).await?;
+    for folder in [ROOT_FOLDER, TORRENT_CACHE_FOLDER] {
+        let path = Path::new(folder);
+        if !path.exists() {
+            create_dir_all(path).await?;
+        }
     }
     Ok(())
 }
@@ -49,6 +55,42 @@ fn parse_env() {
                 .expect("DEBUG_CC_ID is invalid");
         }
     }
+    if let Some(local) = std::env::var_os("BOT_API_LOCAL") {
+        let local = matches!(local.to_string_lossy().as_ref(), "1" | "true");
+        unsafe {
+            LOCAL_BOT_API = local;
+            if local {
+                MAX_FILE_SIZE = LOCAL_API_MAX_FILE_SIZE;
+            }
+        }
+    }
+    if let Some(legacy) = std::env::var_os("LEGACY_ROUND_TRIP") {
+        unsafe {
+            LEGACY_ROUND_TRIP = matches!(legacy.to_string_lossy().as_ref(), "1" | "true");
+        }
+    }
+    if let Some(size) = std::env::var_os("MAX_FILE_SIZE") {
+        let size = parse_size(&size.to_string_lossy()).expect("MAX_FILE_SIZE is invalid");
+        // the hosted bot api refuses to hand out anything larger
+        if !unsafe { LOCAL_BOT_API } && size > PUBLIC_API_MAX_FILE_SIZE {
+            panic!(
+                "MAX_FILE_SIZE is above {}, the limit of the public bot api, set BOT_API_LOCAL to go higher",
+                to_iec(PUBLIC_API_MAX_FILE_SIZE)
+            );
+        }
+        unsafe {
+            MAX_FILE_SIZE = size;
+        }
+    }
+}
+
+/// `BOT_API_URL` points at a self-hosted bot api server
+fn bot_from_env() -> teloxide::Bot {
+    let bot = teloxide::Bot::from_env();
+    match std::env::var("BOT_API_URL") {
+        Ok(url) => bot.set_api_url(url.parse().expect("BOT_API_URL is invalid")),
+        Err(_) => bot,
+    }
 }
 
 pub async fn run() -> Result<()> {
@@ -56,9 +98,7 @@ pub async fn run() -> Result<()> {
     parse_env();
     teloxide::enable_logging!();
 
-    let bot = teloxide::Bot::from_env()
-        .throttle(Limits::default())
-        .auto_send();
+    let bot = bot_from_env().throttle(Limits::default()).auto_send();
     set_up_commands(&bot).await?;
 
     let librarian = Arc::new(Mutex::new(Librarian::new()?));
@@ -0,0 +1,392 @@
+///
+/// sha1 lists shared as zip, gz or tar.gz bundles, unpacked in memory
+///
+/// members are txt, json, csv or tsv lists, anything else is skipped
+///
+use anyhow::{bail, Result};
+use flate2::read::GzDecoder;
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::Path;
+
+use crate::file_kind::full_extension;
+use crate::line_parser::{parse_lines, Sha1Line};
+use crate::parsers::{entity_lines, records_summary, to_iec};
+use crate::sha1_csv::csv2line_mem;
+use crate::sha1_json::parse_sha1_json;
+use crate::text_encoding::decode;
+
+/// entries looked at before the rest is left out
+const MAX_ENTRIES: usize = 200;
+/// a single member larger than this unpacked is skipped
+const MAX_MEMBER_SIZE: u64 = 32 * 1024 * 1024;
+/// unpacking stops once this much has been read
+const MAX_TOTAL_SIZE: u64 = 128 * 1024 * 1024;
+/// lists, and skipped members, named in the chat
+const MEMBERS_SHOWN: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ArchiveKind {
+    Zip,
+    /// a single gzipped list
+    Gzip,
+    TarGz,
+}
+
+impl ArchiveKind {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match full_extension(name).as_str() {
+            "zip" => Some(ArchiveKind::Zip),
+            "gz" => Some(ArchiveKind::Gzip),
+            "tar.gz" | "tgz" => Some(ArchiveKind::TarGz),
+            _ => None,
+        }
+    }
+
+    /// the name without the archive extension
+    pub(crate) fn stem<'a>(&self, name: &'a str) -> &'a str {
+        let ext_len = full_extension(name).len();
+        &name[..name.len() - ext_len - 1]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ListKind {
+    Line,
+    Json,
+    Csv,
+}
+
+impl ListKind {
+    fn from_name(name: &str) -> Option<Self> {
+        match full_extension(name).as_str() {
+            "txt" => Some(ListKind::Line),
+            "json" => Some(ListKind::Json),
+            "csv" | "tsv" => Some(ListKind::Csv),
+            _ => None,
+        }
+    }
+
+    fn parse(&self, bytes: &[u8]) -> Result<Vec<Sha1Line>> {
+        let (text, _) = decode(bytes);
+        let lines = match self {
+            ListKind::Line => text.into_owned(),
+            ListKind::Json => entity_lines(&parse_sha1_json(text.as_bytes())?.0, false),
+            ListKind::Csv => csv2line_mem(&text)?.0,
+        };
+        Ok(parse_lines(&lines).flatten().collect())
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct ArchiveReport {
+    /// member name and its valid lines, in archive order
+    lists: Vec<(String, Vec<Sha1Line>)>,
+    /// member name and why it was left out
+    skipped: Vec<(String, String)>,
+    /// a limit was hit and the rest of the archive never looked at
+    truncated: bool,
+    read: u64,
+}
+
+impl ArchiveReport {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.lists.is_empty()
+    }
+
+    /// lists found
+    pub(crate) fn len(&self) -> usize {
+        self.lists.len()
+    }
+
+    fn add(&mut self, name: String, bytes: &[u8]) {
+        let kind = match ListKind::from_name(&name) {
+            Some(kind) => kind,
+            None => return self.skipped.push((name, "不支持的格式".to_owned())),
+        };
+        match kind.parse(bytes) {
+            Ok(records) if !records.is_empty() => self.lists.push((name, records)),
+            Ok(_) => self.skipped.push((name, "没有有效链接".to_owned())),
+            Err(e) => {
+                log::warn!("invalid list {} in archive: {}", name, e);
+                self.skipped.push((name, "无法解析".to_owned()));
+            }
+        }
+    }
+
+    /// reads one member within the limits, `None` when it was skipped
+    fn take(&mut self, name: &str, declared: u64, reader: impl Read) -> Result<Option<Vec<u8>>> {
+        if declared > MAX_MEMBER_SIZE {
+            self.skipped.push((name.to_owned(), "文件太大".to_owned()));
+            return Ok(None);
+        }
+        // the declared size may lie
+        let limit = MAX_MEMBER_SIZE.min(MAX_TOTAL_SIZE - self.read);
+        let mut bytes = Vec::new();
+        reader.take(limit + 1).read_to_end(&mut bytes)?;
+        self.read += bytes.len() as u64;
+        if bytes.len() as u64 > limit {
+            self.skipped.push((name.to_owned(), "文件太大".to_owned()));
+            self.truncated |= self.read >= MAX_TOTAL_SIZE;
+            return Ok(None);
+        }
+        Ok(Some(bytes))
+    }
+
+    /// whether another entry may be read
+    fn has_room(&mut self, entries: usize) -> bool {
+        if entries >= MAX_ENTRIES || self.read >= MAX_TOTAL_SIZE {
+            self.truncated = true;
+        }
+        !self.truncated
+    }
+
+    /// every list in one, the first copy of each file kept, returns how many were dropped
+    pub(crate) fn merged(self) -> (Vec<Sha1Line>, usize) {
+        let mut seen = HashSet::new();
+        let mut merged = Vec::new();
+        let mut dropped = 0;
+        for (_, records) in self.lists {
+            for record in records {
+                if seen.insert(record.file.unique_key()) {
+                    merged.push(record);
+                } else {
+                    dropped += 1;
+                }
+            }
+        }
+        (merged, dropped)
+    }
+
+    /// every list in a line, then the summary of all of them together
+    pub(crate) fn for_chat(&self, private: bool) -> Result<String> {
+        let mut res = format!("压缩包内共 {} 个列表:\n", self.lists.len());
+        for (name, records) in self.lists.iter().take(MEMBERS_SHOWN) {
+            let size: u64 = records.iter().map(|record| record.file.size()).sum();
+            res.push_str(&format!(
+                "{}: {} 个文件, {}\n",
+                name,
+                records.len(),
+                to_iec(size)
+            ));
+        }
+        if self.lists.len() > MEMBERS_SHOWN {
+            res.push_str("...\n");
+        }
+        if !self.skipped.is_empty() {
+            res.push_str(&format!("跳过 {} 个文件", self.skipped.len()));
+            if private {
+                let skipped: Vec<String> = self
+                    .skipped
+                    .iter()
+                    .take(MEMBERS_SHOWN)
+                    .map(|(name, reason)| format!("{} ({})", name, reason))
+                    .collect();
+                res.push_str(&format!(": {}", skipped.join(", ")));
+                if self.skipped.len() > MEMBERS_SHOWN {
+                    res.push_str(" ...");
+                }
+            }
+            res.push('\n');
+        }
+        if self.truncated {
+            res.push_str("! 压缩包太大, 超出限制的部分没有处理\n");
+        }
+        let summary = records_summary(self.lists.iter().flat_map(|(_, records)| records))?;
+        res.push_str(&format!("\n合计 {}", summary.for_chat(private)));
+        Ok(res)
+    }
+}
+
+fn read_zip(path: &Path, report: &mut ArchiveReport) -> Result<()> {
+    let mut zip = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    for i in 0..zip.len() {
+        if !report.has_room(i) {
+            break;
+        }
+        let file = zip.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        // windows zips keep names in the system code page
+        let name = decode(file.name_raw()).0.into_owned();
+        let declared = file.size();
+        if let Some(bytes) = report.take(&name, declared, file)? {
+            report.add(name, &bytes);
+        }
+    }
+    Ok(())
+}
+
+fn read_gzip(path: &Path, file_name: &str, report: &mut ArchiveReport) -> Result<()> {
+    let decoder = GzDecoder::new(std::fs::File::open(path)?);
+    let name = match decoder.header().and_then(|header| header.filename()) {
+        Some(name) => decode(name).0.into_owned(),
+        None => ArchiveKind::Gzip.stem(file_name).to_owned(),
+    };
+    if let Some(bytes) = report.take(&name, 0, decoder)? {
+        report.add(name, &bytes);
+    }
+    Ok(())
+}
+
+fn read_tar_gz(path: &Path, report: &mut ArchiveReport) -> Result<()> {
+    let mut tar = tar::Archive::new(GzDecoder::new(std::fs::File::open(path)?));
+    for (i, entry) in tar.entries()?.enumerate() {
+        if !report.has_room(i) {
+            break;
+        }
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = decode(&entry.path_bytes()).0.into_owned();
+        let declared = entry.header().size()?;
+        if let Some(bytes) = report.take(&name, declared, entry)? {
+            report.add(name, &bytes);
+        }
+    }
+    Ok(())
+}
+
+/// every list in the archive, parsed, `file_name` is the name the archive was sent under
+pub(crate) fn read_archive(path: &Path, file_name: &str) -> Result<ArchiveReport> {
+    let kind = match ArchiveKind::from_name(file_name) {
+        Some(kind) => kind,
+        None => bail!("not an archive: {}", file_name),
+    };
+    let mut report = ArchiveReport::default();
+    match kind {
+        ArchiveKind::Zip => read_zip(path, &mut report)?,
+        ArchiveKind::Gzip => read_gzip(path, file_name, &mut report)?,
+        ArchiveKind::TarGz => read_tar_gz(path, &mut report)?,
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    /// names are hex digits, the same name gives the same file
+    fn list(names: &[&str]) -> String {
+        names
+            .iter()
+            .map(|name| format!("115://{0}|1|{1}|{1}|dir\n", name, name.repeat(40)))
+            .collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn stems() {
+        assert_eq!(ArchiveKind::from_name("a.ZIP"), Some(ArchiveKind::Zip));
+        assert_eq!(ArchiveKind::from_name("a.tar.gz"), Some(ArchiveKind::TarGz));
+        assert_eq!(ArchiveKind::from_name("a.txt"), None);
+        assert_eq!(ArchiveKind::Gzip.stem("LIST.TXT.GZ"), "LIST.TXT");
+        assert_eq!(ArchiveKind::TarGz.stem("列表.tar.gz"), "列表");
+        assert_eq!(ArchiveKind::TarGz.stem("a.b.tgz"), "a.b");
+        assert_eq!(ArchiveKind::Zip.stem("a.zip"), "a");
+    }
+
+    #[test]
+    fn members_over_the_limits_are_skipped() {
+        let mut report = ArchiveReport::default();
+        // the declared size alone is enough to skip it, nothing is read
+        assert!(report
+            .take("declared", MAX_MEMBER_SIZE + 1, std::io::empty())
+            .unwrap()
+            .is_none());
+        assert_eq!(report.read, 0);
+
+        let lying = std::io::repeat(b'a').take(MAX_MEMBER_SIZE + 10);
+        assert!(report.take("lying", 1, lying).unwrap().is_none());
+        assert_eq!(report.read, MAX_MEMBER_SIZE + 1);
+        assert!(!report.truncated);
+        assert!(report.has_room(1));
+        let names: Vec<_> = report
+            .skipped
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, ["declared", "lying"]);
+
+        // what is left of the total is the limit for the next member
+        report.read = MAX_TOTAL_SIZE - 10;
+        assert_eq!(
+            report
+                .take("fits", 10, &[b'a'; 10][..])
+                .unwrap()
+                .unwrap()
+                .len(),
+            10
+        );
+        assert!(!report.has_room(2));
+        assert!(report.truncated);
+
+        let mut report = ArchiveReport {
+            read: MAX_TOTAL_SIZE - 10,
+            ..ArchiveReport::default()
+        };
+        assert!(report.take("over", 11, &[b'a'; 11][..]).unwrap().is_none());
+        assert!(report.truncated);
+        assert!(!ArchiveReport::default().has_room(MAX_ENTRIES));
+    }
+
+    #[test]
+    fn reads_zip_and_gz() {
+        let dir = temp_dir("archive");
+
+        let zip_path = dir.join("lists.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+        let options = zip::write::FileOptions::default();
+        zip.add_directory("sub/", options).unwrap();
+        zip.start_file("sub/a.txt", options).unwrap();
+        zip.write_all(list(&["a", "b"]).as_bytes()).unwrap();
+        zip.start_file("b.txt", options).unwrap();
+        zip.write_all(list(&["b", "c"]).as_bytes()).unwrap();
+        zip.start_file("readme.md", options).unwrap();
+        zip.write_all(b"hello").unwrap();
+        zip.start_file("empty.txt", options).unwrap();
+        zip.finish().unwrap();
+
+        let report = read_archive(&zip_path, "lists.zip").unwrap();
+        assert_eq!(report.len(), 2);
+        let skipped: Vec<_> = report
+            .skipped
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(skipped, ["readme.md", "empty.txt"]);
+        let (merged, dropped) = report.merged();
+        let names: Vec<_> = merged.iter().map(|r| r.file.name()).collect();
+        assert_eq!((names, dropped), (vec!["a", "b", "c"], 1));
+
+        for (header_name, expected) in [(Some("inner.txt"), "inner.txt"), (None, "list.txt")] {
+            let gz_path = dir.join("list.txt.gz");
+            let mut builder = flate2::GzBuilder::new();
+            if let Some(name) = header_name {
+                builder = builder.filename(name);
+            }
+            let mut gz = builder.write(
+                std::fs::File::create(&gz_path).unwrap(),
+                flate2::Compression::default(),
+            );
+            gz.write_all(list(&["a"]).as_bytes()).unwrap();
+            gz.finish().unwrap();
+
+            let report = read_archive(&gz_path, "list.txt.gz").unwrap();
+            assert_eq!(report.lists.len(), 1);
+            assert_eq!(report.lists[0].0, expected);
+        }
+        assert!(read_archive(&zip_path, "lists.rar").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
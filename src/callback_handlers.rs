@@ -1,6 +1,19 @@
 use crate::{
+    archive::{read_archive, ArchiveKind},
+    conflicts::find_conflicts,
+    decryption::{PreidForm, CODEC},
+    du::{du_report, du_table, DU_REPORT_DEPTH, DU_TABLE_ROWS},
     global::*,
-    parsers::{dedup_filerepr_file, json2line, line2json, line_strip_dir_info},
+    lint::{fix_entity, fix_lines, lint_entity, lint_lines},
+    list_ops::{entity_bytes, list_bytes, list_extension, load_entity, load_list},
+    parsers::{
+        dedup_filerepr_file, encrypt_line_file, json2line, line2json, line_errors_report,
+        line_strip_dir_info, path_to_sha1_entity, write_all_to_file,
+    },
+    repair::repair_file,
+    sha1_csv::{json2csv, line2csv},
+    sha1_json::{export_sha1_json, JsonFormat},
+    torrent::TorrentMeta,
 };
 use anyhow::Result;
 use scopeguard::defer;
@@ -8,10 +21,10 @@ use std::path::{Path, PathBuf};
 use teloxide::{
     prelude::Requester,
     requests::HasPayload,
-    types::{InputFile, Message},
+    types::{InputFile, Message, ParseMode},
+    utils::html::escape,
 };
 
-
 use teloxide::prelude::{CallbackQuery, UpdateWithCx};
 use tokio::fs::read_dir;
 
@@ -82,7 +95,194 @@ pub(crate) async fn callback_to_dedup(bot: &Bot, msg: &Message, id_suffix: &str)
     Ok(found_cache)
 }
 
-pub(crate) async fn callback_to_line(bot: &Bot, msg: &Message, id_suffix: &str) -> Result<bool> {
+pub(crate) async fn callback_conflicts(bot: &Bot, msg: &Message, id_suffix: &str) -> Result<bool> {
+    let mut found_cache = false;
+    if let Some(cache) = find_cache(id_suffix).await? {
+        found_cache = true;
+        let filename = &cache.name;
+        let mut new_file_path = cache.path.clone();
+        new_file_path.pop();
+        let stem = match filename.rsplit_once('.') {
+            Some((stem, _)) => stem,
+            None => filename,
+        };
+        new_file_path.push(stem.to_string() + "_冲突报告.txt");
+
+        defer! {
+            if cache.path.exists(){
+                let _ = std::fs::remove_file(&cache.path);
+            }
+            if new_file_path.exists(){
+                let _ = std::fs::remove_file(&new_file_path);
+            }
+        }
+
+        let (records, _) = load_list(&cache.path).await?;
+        let conflicts = find_conflicts(&records);
+        write_all_to_file(&new_file_path, conflicts.details(&records).as_bytes()).await?;
+
+        let input_file = InputFile::File(new_file_path.to_path_buf());
+        let mut req = bot.send_document(msg.chat_id(), input_file);
+        let payload = req.payload_mut();
+        payload.reply_to_message_id = Some(msg.id);
+        payload.caption = Some(conflicts.to_string());
+        req.await?;
+    }
+    Ok(found_cache)
+}
+
+pub(crate) async fn callback_lint_fix(bot: &Bot, msg: &Message, id_suffix: &str) -> Result<bool> {
+    let mut found_cache = false;
+    if let Some(cache) = find_cache(id_suffix).await? {
+        found_cache = true;
+        let filename = &cache.name;
+        let mut new_file_path = cache.path.clone();
+        new_file_path.pop();
+        let stem = match filename.rsplit_once('.') {
+            Some((stem, _)) => stem,
+            None => filename,
+        };
+
+        let (content, format, dropped, unfixable) = if filename.ends_with(".json") {
+            let (mut entity, format) = load_entity(&cache.path).await?;
+            let dropped = fix_entity(&mut entity);
+            let unfixable = lint_entity(&entity).unfixable();
+            (entity_bytes(&entity, format)?, format, dropped, unfixable)
+        } else {
+            let (records, format) = load_list(&cache.path).await?;
+            let (records, dropped) = fix_lines(records);
+            let unfixable = lint_lines(&records).unfixable();
+            (list_bytes(records, format)?, format, dropped, unfixable)
+        };
+        new_file_path.push(format!("{}_已修复.{}", stem, list_extension(format)));
+
+        defer! {
+            if cache.path.exists(){
+                let _ = std::fs::remove_file(&cache.path);
+            }
+            if new_file_path.exists(){
+                let _ = std::fs::remove_file(&new_file_path);
+            }
+        }
+
+        write_all_to_file(&new_file_path, &content).await?;
+        let mut caption = format!("已修复文件名, 删除 {} 个空文件或 sha1 有误的文件", dropped);
+        if unfixable != 0 {
+            caption = format!("{}\n仍有 {} 处无法自动修复的问题", caption, unfixable);
+        }
+
+        let input_file = InputFile::File(new_file_path.to_path_buf());
+        let mut req = bot.send_document(msg.chat_id(), input_file);
+        let payload = req.payload_mut();
+        payload.reply_to_message_id = Some(msg.id);
+        payload.caption = Some(caption);
+        req.await?;
+    }
+    Ok(found_cache)
+}
+
+/// every list of an archive in one, duplicates dropped
+pub(crate) async fn callback_archive_merge(
+    bot: &Bot,
+    msg: &Message,
+    id_suffix: &str,
+) -> Result<bool> {
+    let mut found_cache = false;
+    if let Some(cache) = find_cache(id_suffix).await? {
+        found_cache = true;
+        let filename = &cache.name;
+        let stem = match ArchiveKind::from_name(filename) {
+            Some(kind) => kind.stem(filename),
+            None => filename,
+        };
+        let mut new_file_path = cache.path.clone();
+        new_file_path.pop();
+        new_file_path.push(stem.to_string() + "_合并.txt");
+
+        defer! {
+            if cache.path.exists(){
+                let _ = std::fs::remove_file(&cache.path);
+            }
+            if new_file_path.exists(){
+                let _ = std::fs::remove_file(&new_file_path);
+            }
+        }
+
+        let report = read_archive(&cache.path, filename)?;
+        let lists = report.len();
+        let (records, dropped) = report.merged();
+        let files = records.len();
+        write_all_to_file(&new_file_path, &list_bytes(records, None)?).await?;
+
+        let input_file = InputFile::File(new_file_path.to_path_buf());
+        let mut req = bot.send_document(msg.chat_id(), input_file);
+        let payload = req.payload_mut();
+        payload.reply_to_message_id = Some(msg.id);
+        payload.caption = Some(format!(
+            "合并了 {} 个列表, 共 {} 个文件, 去掉重复 {} 个",
+            lists, files, dropped
+        ));
+        req.await?;
+    }
+    Ok(found_cache)
+}
+
+pub(crate) async fn callback_repair(bot: &Bot, msg: &Message, id_suffix: &str) -> Result<bool> {
+    let mut found_cache = false;
+    if let Some(cache) = find_cache(id_suffix).await? {
+        found_cache = true;
+        let filename = &cache.name;
+        let stem = match filename.rsplit_once('.') {
+            Some((stem, _)) => stem,
+            None => filename,
+        };
+        let mut new_file_path = cache.path.clone();
+        new_file_path.pop();
+        let mut log_path = new_file_path.clone();
+        new_file_path.push(stem.to_string() + "_已修复格式.txt");
+        log_path.push(stem.to_string() + "_修复记录.txt");
+
+        defer! {
+            if cache.path.exists(){
+                let _ = std::fs::remove_file(&cache.path);
+            }
+            if new_file_path.exists(){
+                let _ = std::fs::remove_file(&new_file_path);
+            }
+            if log_path.exists(){
+                let _ = std::fs::remove_file(&log_path);
+            }
+        }
+
+        let report = repair_file(&cache.path, &new_file_path, &log_path).await?;
+        if report.is_empty() {
+            bot.send_message(msg.chat_id(), "没有发现可以自动修复的问题")
+                .await?;
+            return Ok(true);
+        }
+
+        for (path, caption) in [
+            (&new_file_path, Some(report.to_string())),
+            (&log_path, None),
+        ] {
+            let input_file = InputFile::File(path.to_path_buf());
+            let mut req = bot.send_document(msg.chat_id(), input_file);
+            let payload = req.payload_mut();
+            payload.reply_to_message_id = Some(msg.id);
+            payload.caption = caption;
+            req.await?;
+        }
+    }
+    Ok(found_cache)
+}
+
+/// `folder_lines` also writes the empty folders, as `||||folder` lines
+pub(crate) async fn callback_to_line(
+    bot: &Bot,
+    msg: &Message,
+    id_suffix: &str,
+    folder_lines: bool,
+) -> Result<bool> {
     let mut found_cache = false;
     if let Some(cache) = find_cache(id_suffix).await? {
         found_cache = true;
@@ -106,7 +306,7 @@ pub(crate) async fn callback_to_line(bot: &Bot, msg: &Message, id_suffix: &str)
             }
         }
 
-        json2line(&cache.path, &new_file_path).await?;
+        json2line(&cache.path, &new_file_path, folder_lines).await?;
 
         let input_file = InputFile::File(new_file_path.to_path_buf());
         let mut req = bot.send_document(msg.chat_id(), input_file);
@@ -141,7 +341,129 @@ pub(crate) async fn callback_to_json(bot: &Bot, msg: &Message, id_suffix: &str)
             }
         }
 
-        line2json(&cache.path, &new_file_path).await?;
+        line2json(&cache.path, &new_file_path, unsafe { !LEGACY_ROUND_TRIP }).await?;
+
+        let input_file = InputFile::File(new_file_path.to_path_buf());
+        let mut req = bot.send_document(msg.chat_id(), input_file);
+        let payload = req.payload_mut();
+        payload.reply_to_message_id = Some(msg.id);
+        req.await?;
+    }
+    Ok(found_cache)
+}
+
+pub(crate) async fn callback_json_export(
+    bot: &Bot,
+    msg: &Message,
+    id_suffix: &str,
+    format: JsonFormat,
+) -> Result<bool> {
+    let mut found_cache = false;
+    if let Some(cache) = find_cache(id_suffix).await? {
+        found_cache = true;
+        let filename = &cache.name;
+        let mut new_file_path = cache.path.clone();
+        new_file_path.pop();
+        let stem = filename.strip_suffix(".json").unwrap_or(filename);
+        new_file_path.push(stem.to_string() + format.file_suffix() + ".json");
+
+        defer! {
+            if cache.path.exists(){
+                let _ = std::fs::remove_file(&cache.path);
+            }
+            if new_file_path.exists(){
+                let _ = std::fs::remove_file(&new_file_path);
+            }
+        }
+
+        let (entity, _) = path_to_sha1_entity(&cache.path).await?;
+        write_all_to_file(&new_file_path, &export_sha1_json(&entity, format)?).await?;
+
+        let input_file = InputFile::File(new_file_path.to_path_buf());
+        let mut req = bot.send_document(msg.chat_id(), input_file);
+        let payload = req.payload_mut();
+        payload.reply_to_message_id = Some(msg.id);
+        req.await?;
+    }
+    Ok(found_cache)
+}
+
+pub(crate) async fn callback_to_csv(
+    bot: &Bot,
+    msg: &Message,
+    id_suffix: &str,
+    tsv: bool,
+) -> Result<bool> {
+    let mut found_cache = false;
+    if let Some(cache) = find_cache(id_suffix).await? {
+        found_cache = true;
+        let filename = &cache.name;
+        let mut new_file_path = cache.path.clone();
+        new_file_path.pop();
+        let is_json = filename.ends_with(".json");
+        let stem = filename
+            .strip_suffix(".json")
+            .or_else(|| filename.strip_suffix(".txt"))
+            .unwrap_or(filename);
+        new_file_path.push(stem.to_string() + if tsv { ".tsv" } else { ".csv" });
+
+        defer! {
+            if cache.path.exists(){
+                let _ = std::fs::remove_file(&cache.path);
+            }
+            if new_file_path.exists(){
+                let _ = std::fs::remove_file(&new_file_path);
+            }
+        }
+
+        if is_json {
+            let (entity, _) = path_to_sha1_entity(&cache.path).await?;
+            json2csv(&entity, &new_file_path, tsv)?;
+        } else {
+            line2csv(&cache.path, &new_file_path, tsv).await?;
+        }
+
+        let input_file = InputFile::File(new_file_path.to_path_buf());
+        let mut req = bot.send_document(msg.chat_id(), input_file);
+        let payload = req.payload_mut();
+        payload.reply_to_message_id = Some(msg.id);
+        req.await?;
+    }
+    Ok(found_cache)
+}
+
+pub(crate) async fn callback_du(bot: &Bot, msg: &Message, id_suffix: &str) -> Result<bool> {
+    let mut found_cache = false;
+    if let Some(cache) = find_cache(id_suffix).await? {
+        found_cache = true;
+        let filename = &cache.name;
+        let mut new_file_path = cache.path.clone();
+        new_file_path.pop();
+        let stem = match filename.rsplit_once('.') {
+            Some((stem, _)) => stem,
+            None => filename,
+        };
+        new_file_path.push(stem.to_string() + "_目录大小.txt");
+
+        defer! {
+            if cache.path.exists(){
+                let _ = std::fs::remove_file(&cache.path);
+            }
+            if new_file_path.exists(){
+                let _ = std::fs::remove_file(&new_file_path);
+            }
+        }
+
+        let (entity, _) = load_entity(&cache.path).await?;
+        let table = format!("<code>{}</code>", escape(&du_table(&entity, DU_TABLE_ROWS)));
+        let report = du_report(&entity, DU_REPORT_DEPTH);
+        write_all_to_file(&new_file_path, report.as_bytes()).await?;
+
+        let mut req = bot.send_message(msg.chat_id(), table);
+        let payload = req.payload_mut();
+        payload.reply_to_message_id = Some(msg.id);
+        payload.parse_mode = Some(ParseMode::Html);
+        req.await?;
 
         let input_file = InputFile::File(new_file_path.to_path_buf());
         let mut req = bot.send_document(msg.chat_id(), input_file);
@@ -188,6 +510,151 @@ pub(crate) async fn callback_line_strip_dir(bot: &Bot, msg: &Message, id_suffix:
     Ok(found_cache)
 }
 
+pub(crate) async fn callback_encrypt(bot: &Bot, msg: &Message, id_suffix: &str) -> Result<bool> {
+    let mut found_cache = false;
+    if let Some(cache) = find_cache(id_suffix).await? {
+        found_cache = true;
+        let stem = cache.name.strip_suffix(".txt").unwrap_or(&cache.name);
+        let mut new_file_path = cache.path.clone();
+        new_file_path.pop();
+        new_file_path.push(format!("{}_已加密.txt", stem));
+
+        defer! {
+            if cache.path.exists(){
+                let _ = std::fs::remove_file(&cache.path);
+            }
+            if new_file_path.exists(){
+                let _ = std::fs::remove_file(&new_file_path);
+            }
+        }
+
+        let encrypted = encrypt_line_file(&cache.path, &new_file_path).await?;
+        if encrypted == 0 {
+            bot.send_message(msg.chat_id(), "没有需要加密的块 sha1")
+                .await?;
+            return Ok(true);
+        }
+
+        let input_file = InputFile::File(new_file_path.to_path_buf());
+        let mut req = bot.send_document(msg.chat_id(), input_file);
+        let payload = req.payload_mut();
+        payload.reply_to_message_id = Some(msg.id);
+        payload.caption = Some(format!(
+            "加密了 {} 个块 sha1, 加密方式: {} ({})",
+            encrypted,
+            CODEC,
+            PreidForm::Short
+        ));
+        req.await?;
+    }
+    Ok(found_cache)
+}
+
+pub(crate) async fn callback_line_errors(
+    bot: &Bot,
+    msg: &Message,
+    id_suffix: &str,
+) -> Result<bool> {
+    let mut found_cache = false;
+    if let Some(cache) = find_cache(id_suffix).await? {
+        found_cache = true;
+        let mut new_file_path = cache.path.clone();
+        new_file_path.pop();
+        new_file_path.push("errors.txt");
+
+        defer! {
+            if cache.path.exists(){
+                let _ = std::fs::remove_file(&cache.path);
+            }
+            if new_file_path.exists(){
+                let _ = std::fs::remove_file(&new_file_path);
+            }
+        }
+
+        let count = line_errors_report(&cache.path, &new_file_path).await?;
+        if count == 0 {
+            return Ok(found_cache);
+        }
+
+        let input_file = InputFile::File(new_file_path.to_path_buf());
+        let mut req = bot.send_document(msg.chat_id(), input_file);
+        let payload = req.payload_mut();
+        payload.reply_to_message_id = Some(msg.id);
+        payload.caption = Some(format!("共 {} 行格式错误", count));
+        req.await?;
+    }
+    Ok(found_cache)
+}
+
+pub(crate) async fn callback_torrent_list(
+    bot: &Bot,
+    msg: &Message,
+    id_suffix: &str,
+    json: bool,
+) -> Result<bool> {
+    let mut found_cache = false;
+    if let Some(cache) = find_cache(id_suffix).await? {
+        found_cache = true;
+        let filename = &cache.name;
+        let mut new_file_path = cache.path.clone();
+        new_file_path.pop();
+        let stem = filename.strip_suffix(".torrent").unwrap_or(filename);
+        let new_filename = if json {
+            stem.to_string() + "_文件列表.json"
+        } else {
+            stem.to_string() + "_文件列表.txt"
+        };
+        new_file_path.push(new_filename);
+
+        defer! {
+            if cache.path.exists(){
+                let _ = std::fs::remove_file(&cache.path);
+            }
+            if new_file_path.exists(){
+                let _ = std::fs::remove_file(&new_file_path);
+            }
+        }
+
+        let meta = TorrentMeta::from_path(&cache.path).await?;
+        let content = if json {
+            meta.file_list_json()?
+        } else {
+            meta.file_list_txt()
+        };
+        write_all_to_file(&new_file_path, content.as_bytes()).await?;
+
+        let input_file = InputFile::File(new_file_path.to_path_buf());
+        let mut req = bot.send_document(msg.chat_id(), input_file);
+        let payload = req.payload_mut();
+        payload.reply_to_message_id = Some(msg.id);
+        req.await?;
+    }
+    Ok(found_cache)
+}
+
+pub(crate) async fn callback_torrent_details(
+    bot: &Bot,
+    msg: &Message,
+    id_suffix: &str,
+) -> Result<bool> {
+    let mut found_cache = false;
+    if let Some(cache) = find_cache(id_suffix).await? {
+        found_cache = true;
+        defer! {
+            if cache.path.exists(){
+                let _ = std::fs::remove_file(&cache.path);
+            }
+        }
+
+        let meta = TorrentMeta::from_path(&cache.path).await?;
+        let mut req = bot.send_message(msg.chat_id(), meta.details());
+        let payload = req.payload_mut();
+        payload.reply_to_message_id = Some(msg.id);
+        req.await?;
+    }
+    Ok(found_cache)
+}
+
 pub(crate) async fn callback_handler(cx: UpdateWithCx<Bot, CallbackQuery>) -> Result<()> {
     let UpdateWithCx {
         requester: bot,
@@ -201,9 +668,26 @@ pub(crate) async fn callback_handler(cx: UpdateWithCx<Bot, CallbackQuery>) -> Re
 
         let found_cache = match &version[..2] {
             "2j" => callback_to_json(bot, msg, &version[2..]).await?,
-            "2l" => callback_to_line(bot, msg, &version[2..]).await?,
+            "2l" => callback_to_line(bot, msg, &version[2..], false).await?,
+            "2f" => callback_to_line(bot, msg, &version[2..], true).await?,
+            "2c" => callback_to_csv(bot, msg, &version[2..], false).await?,
+            "2t" => callback_to_csv(bot, msg, &version[2..], true).await?,
+            "du" => callback_du(bot, msg, &version[2..]).await?,
             "ls" => callback_line_strip_dir(bot, msg, &version[2..]).await?,
             "ld" => callback_to_dedup(bot, msg, &version[2..]).await?,
+            "cf" => callback_conflicts(bot, msg, &version[2..]).await?,
+            "lf" => callback_lint_fix(bot, msg, &version[2..]).await?,
+            "rp" => callback_repair(bot, msg, &version[2..]).await?,
+            "am" => callback_archive_merge(bot, msg, &version[2..]).await?,
+            "le" => callback_line_errors(bot, msg, &version[2..]).await?,
+            "ec" => callback_encrypt(bot, msg, &version[2..]).await?,
+            "tt" => callback_torrent_list(bot, msg, &version[2..], false).await?,
+            "tj" => callback_torrent_list(bot, msg, &version[2..], true).await?,
+            "ti" => callback_torrent_details(bot, msg, &version[2..]).await?,
+            "js" | "jf" | "ja" | "jc" => {
+                let format = JsonFormat::from_code(&version[..2]).unwrap();
+                callback_json_export(bot, msg, &version[2..], format).await?
+            }
             _ => {
                 bot.answer_callback_query(&query.id).await?;
                 let text = msg.text().unwrap_or("").to_owned() + "\n发生了错误..";
@@ -0,0 +1,218 @@
+///
+/// copies of one file under different names, and one name used by different files
+///
+/// content is the same when sha1 and size are, the block sha1 is not compared
+///
+use anyhow::bail;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+
+use crate::line_parser::Sha1Line;
+use crate::parsers::{to_iec, Sha1Hash};
+
+/// which entry of a group survives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum KeepPolicy {
+    First,
+    Last,
+    /// ties go to the first
+    Largest,
+    /// fewest folders, then the shortest path
+    Shallowest,
+}
+
+impl FromStr for KeepPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "first" | "最早" => KeepPolicy::First,
+            "last" | "最后" => KeepPolicy::Last,
+            "largest" | "最大" => KeepPolicy::Largest,
+            "shallow" | "最浅" => KeepPolicy::Shallowest,
+            _ => bail!("unknown keep policy: {}", s),
+        })
+    }
+}
+
+impl fmt::Display for KeepPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            KeepPolicy::First => "每组保留最早出现的",
+            KeepPolicy::Last => "每组保留最后出现的",
+            KeepPolicy::Largest => "每组保留最大的",
+            KeepPolicy::Shallowest => "每组保留目录最浅的",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl KeepPolicy {
+    fn pick(&self, group: &[usize], records: &[Sha1Line]) -> usize {
+        let first = group[0];
+        match self {
+            KeepPolicy::First => first,
+            KeepPolicy::Last => *group.last().unwrap_or(&first),
+            KeepPolicy::Largest => *group
+                .iter()
+                .min_by_key(|i| Reverse(records[**i].file.size()))
+                .unwrap_or(&first),
+            KeepPolicy::Shallowest => *group
+                .iter()
+                .min_by_key(|i| {
+                    let record = &records[**i];
+                    (record.folders.len(), path_of(record).chars().count())
+                })
+                .unwrap_or(&first),
+        }
+    }
+}
+
+fn content_of(record: &Sha1Line) -> (u64, Sha1Hash) {
+    (record.file.size(), *record.file.sha1())
+}
+
+fn path_of(record: &Sha1Line) -> String {
+    let mut path = record.folders.join("/");
+    if !path.is_empty() {
+        path.push('/');
+    }
+    path.push_str(record.file.name());
+    path
+}
+
+/// indexes sharing `key` but not `other`, in the order they first show up
+fn groups<K, O>(
+    records: &[Sha1Line],
+    indexes: impl Iterator<Item = usize>,
+    key: impl Fn(&Sha1Line) -> K,
+    other: impl Fn(&Sha1Line) -> O,
+) -> Vec<Vec<usize>>
+where
+    K: Hash + Eq,
+    O: Hash + Eq,
+{
+    let mut position: HashMap<K, usize> = HashMap::new();
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for i in indexes {
+        match position.get(&key(&records[i])) {
+            Some(at) => groups[*at].push(i),
+            None => {
+                position.insert(key(&records[i]), groups.len());
+                groups.push(vec![i]);
+            }
+        }
+    }
+    groups.retain(|group| {
+        let others: HashSet<O> = group.iter().map(|i| other(&records[*i])).collect();
+        others.len() > 1
+    });
+    groups
+}
+
+pub(crate) struct ConflictReport {
+    /// same content under different paths
+    renamed: Vec<Vec<usize>>,
+    /// same path with different content
+    conflicting: Vec<Vec<usize>>,
+}
+
+pub(crate) fn find_conflicts(records: &[Sha1Line]) -> ConflictReport {
+    ConflictReport {
+        renamed: groups(records, 0..records.len(), content_of, path_of),
+        conflicting: groups(records, 0..records.len(), path_of, content_of),
+    }
+}
+
+impl ConflictReport {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.renamed.is_empty() && self.conflicting.is_empty()
+    }
+
+    /// every group with its entries, for a report file
+    pub(crate) fn details(&self, records: &[Sha1Line]) -> String {
+        let mut res = format!(
+            "同内容不同名 (sha1 和大小相同): {} 组\n",
+            self.renamed.len()
+        );
+        for (n, group) in self.renamed.iter().enumerate() {
+            let (size, sha1) = content_of(&records[group[0]]);
+            res.push_str(&format!("\n[{}] {} {}\n", n + 1, to_iec(size), sha1));
+            for i in group {
+                let record = &records[*i];
+                res.push_str(&format!(
+                    "    第 {} 行: {}\n",
+                    record.line_number,
+                    path_of(record)
+                ));
+            }
+        }
+
+        res.push_str(&format!(
+            "\n\n同名不同内容: {} 组\n",
+            self.conflicting.len()
+        ));
+        for (n, group) in self.conflicting.iter().enumerate() {
+            res.push_str(&format!("\n[{}] {}\n", n + 1, path_of(&records[group[0]])));
+            for i in group {
+                let record = &records[*i];
+                let (size, sha1) = content_of(record);
+                res.push_str(&format!(
+                    "    第 {} 行: {} {}\n",
+                    record.line_number,
+                    to_iec(size),
+                    sha1
+                ));
+            }
+        }
+        res
+    }
+}
+
+impl fmt::Display for ConflictReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let files = |groups: &Vec<Vec<usize>>| groups.iter().map(Vec::len).sum::<usize>();
+        write!(
+            f,
+            "同内容不同名 {} 组 ({} 个文件), 同名不同内容 {} 组 ({} 个文件)",
+            self.renamed.len(),
+            files(&self.renamed),
+            self.conflicting.len(),
+            files(&self.conflicting)
+        )
+    }
+}
+
+/// one entry per group is kept, conflicting versions first, then renamed copies among what is left,
+/// returns the remaining lines in their order and how many were dropped
+pub(crate) fn resolve_conflicts(
+    records: Vec<Sha1Line>,
+    policy: KeepPolicy,
+) -> (Vec<Sha1Line>, usize) {
+    let mut dropped: HashSet<usize> = HashSet::new();
+    let drop_losers = |groups: Vec<Vec<usize>>, dropped: &mut HashSet<usize>| {
+        for group in groups {
+            let keep = policy.pick(&group, &records);
+            dropped.extend(group.into_iter().filter(|i| *i != keep));
+        }
+    };
+
+    // a losing version may still have a copy elsewhere that now gets to stay
+    let conflicting = groups(&records, 0..records.len(), path_of, content_of);
+    drop_losers(conflicting, &mut dropped);
+    let left = (0..records.len()).filter(|i| !dropped.contains(i));
+    let renamed = groups(&records, left, content_of, path_of);
+    drop_losers(renamed, &mut dropped);
+
+    let count = dropped.len();
+    let kept = records
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !dropped.contains(i))
+        .map(|(_, record)| record)
+        .collect();
+    (kept, count)
+}
@@ -1,21 +1,139 @@
 use anyhow::anyhow;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use lazy_static::lazy_static;
-use openssl::symm::{decrypt, Cipher};
+use openssl::symm::{decrypt, encrypt, Cipher};
 use regex::Regex;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::parsers::Sha1Hash;
+
 const KEY: &[u8; 16] = b"zhshimima1112221";
 const COMMON_SUFFIX: &str = "42IcwVjnnGHZB9ehzW+Pew==";
+/// the hex digits are padded with zeros to whole blocks before encrypting
+const PLAIN_LEN: usize = 48;
+/// pkcs7 adds a whole block to that
+const CIPHER_LEN: usize = 64;
 lazy_static! {
     static ref CIPHER: Cipher = Cipher::aes_128_ecb();
     static ref PATH_ID_REGEX: Regex = Regex::new(r":\d*?/").unwrap();
 }
-pub(crate) fn preid_decrypt(data: &str) -> Result<String> {
-    let content = base64::decode(data.to_owned() + COMMON_SUFFIX)?;
-    let res = decrypt(*CIPHER, KEY, None, &content)?;
-    Ok(std::str::from_utf8(&res)?
-        .trim_end_matches('\x00')
-        .to_string())
+
+/// how a preid is written down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum PreidForm {
+    /// the last block, the same for every preid of a key, is left out
+    Short,
+    /// the whole ciphertext in base64
+    Full,
+}
+
+impl fmt::Display for PreidForm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PreidForm::Short => "省略末尾块",
+            PreidForm::Full => "完整 base64",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// how 115 encrypts the block sha1 into a preid, aes-128-ecb over its hex digits, base64 encoded,
+/// read in either form
+pub(crate) struct PreidCodec {
+    pub(crate) name: &'static str,
+    key: &'static [u8; 16],
+    /// the base64 of the last block, cut from short preids
+    suffix: &'static str,
+}
+
+/// the only scheme known so far
+pub(crate) const CODEC: PreidCodec = PreidCodec {
+    name: "115 导出",
+    key: KEY,
+    suffix: COMMON_SUFFIX,
+};
+
+impl PreidCodec {
+    /// the form of `preid` when it is shaped like one
+    pub(crate) fn form_of(&self, preid: &str) -> Option<PreidForm> {
+        let fits = |full: &str| {
+            base64::decode(full)
+                .map(|bytes| bytes.len() == CIPHER_LEN)
+                .unwrap_or(false)
+        };
+        if preid.ends_with(self.suffix) && fits(preid) {
+            Some(PreidForm::Full)
+        } else if fits(&(preid.to_owned() + self.suffix)) {
+            Some(PreidForm::Short)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn decrypt(&self, preid: &str) -> Result<(Sha1Hash, PreidForm)> {
+        let form = match self.form_of(preid) {
+            Some(form) => form,
+            None => bail!("not a preid of {}: {}", self.name, preid),
+        };
+        let full = match form {
+            PreidForm::Short => preid.to_owned() + self.suffix,
+            PreidForm::Full => preid.to_owned(),
+        };
+        let res = decrypt(*CIPHER, self.key, None, &base64::decode(full)?)?;
+        let hex = std::str::from_utf8(&res)?.trim_end_matches('\x00');
+        let block = hex
+            .parse()
+            .map_err(|_| anyhow!("decrypted preid is not a sha1: {}", hex))?;
+        Ok((block, form))
+    }
+
+    pub(crate) fn encrypt(&self, block: &Sha1Hash, form: PreidForm) -> Result<String> {
+        let mut plain = block.to_uppercase().into_bytes();
+        plain.resize(PLAIN_LEN, 0);
+        let encoded = base64::encode(encrypt(*CIPHER, self.key, None, &plain)?);
+        match form {
+            PreidForm::Full => Ok(encoded),
+            PreidForm::Short => match encoded.strip_suffix(self.suffix) {
+                Some(preid) => Ok(preid.to_owned()),
+                None => bail!("preid does not end with the suffix of {}", self.name),
+            },
+        }
+    }
+}
+
+impl fmt::Display for PreidCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// shaped like a preid
+pub(crate) fn is_preid(s: &str) -> bool {
+    CODEC.form_of(s).is_some()
+}
+
+/// decrypts the preids of a list and keeps count of the form they were written in
+#[derive(Default)]
+pub(crate) struct PreidDecoder {
+    used: BTreeMap<PreidForm, usize>,
 }
+
+impl PreidDecoder {
+    pub(crate) fn decrypt(&mut self, preid: &str) -> Result<Sha1Hash> {
+        let (block, form) = CODEC.decrypt(preid)?;
+        *self.used.entry(form).or_default() += 1;
+        Ok(block)
+    }
+
+    /// the form most preids were written in
+    pub(crate) fn detected(&self) -> Option<PreidForm> {
+        // ties go to the short form
+        let (form, _) = self.used.iter().rev().max_by_key(|(_, used)| **used)?;
+        Some(*form)
+    }
+}
+
 pub(crate) fn format_path_str(path_str: &str) -> Result<String> {
     let path_str = path_str
         .replace(" ", "_")
@@ -29,3 +147,80 @@ pub(crate) fn format_path_str(path_str: &str) -> Result<String> {
         .0;
     Ok(path_str.to_string())
 }
+
+/// what came of decrypting a list
+#[derive(Default)]
+pub(crate) struct DecryptReport {
+    pub(crate) decrypted: usize,
+    pub(crate) form: Option<PreidForm>,
+    /// every line left out and why
+    pub(crate) failed: Vec<String>,
+}
+
+impl DecryptReport {
+    pub(crate) fn details(&self) -> String {
+        let mut res = self.failed.join("\n");
+        res.push('\n');
+        res
+    }
+}
+
+impl fmt::Display for DecryptReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "解密 {} 行", self.decrypted)?;
+        if let Some(form) = self.form {
+            write!(f, ", 加密方式: {} ({})", CODEC, form)?;
+        }
+        if !self.failed.is_empty() {
+            write!(f, "\n! {} 行无法解密, 见失败报告", self.failed.len())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: &str = "0123456789ABCDEF0123456789ABCDEF01234567";
+    const PREID: &str = "gByoftr5SYpf+jtQRwgHf4AcqH7a+UmKX/o7UEcIB3/Gk6uBlHEC89Z09QZByUp3";
+
+    #[test]
+    fn decrypts_a_known_preid_in_both_forms() {
+        let block: Sha1Hash = BLOCK.parse().unwrap();
+        assert_eq!(CODEC.decrypt(PREID).unwrap(), (block, PreidForm::Short));
+        let full = PREID.to_owned() + COMMON_SUFFIX;
+        assert_eq!(CODEC.decrypt(&full).unwrap(), (block, PreidForm::Full));
+    }
+
+    #[test]
+    fn encrypt_round_trips() {
+        let block: Sha1Hash = BLOCK.parse().unwrap();
+        assert_eq!(CODEC.encrypt(&block, PreidForm::Short).unwrap(), PREID);
+        for form in [PreidForm::Short, PreidForm::Full] {
+            let preid = CODEC.encrypt(&block, form).unwrap();
+            assert_eq!(CODEC.decrypt(&preid).unwrap(), (block, form));
+        }
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_preid() {
+        assert_eq!(CODEC.form_of(&PREID[4..]), None);
+        assert_eq!(CODEC.form_of(BLOCK), None);
+        assert_eq!(CODEC.form_of("not base64!"), None);
+        assert!(PreidDecoder::default().decrypt(&PREID[..60]).is_err());
+    }
+
+    #[test]
+    fn reports_the_form_used_most() {
+        let mut decoder = PreidDecoder::default();
+        for block in ["1111111111111111111111111111111111111111", BLOCK] {
+            let preid = CODEC
+                .encrypt(&block.parse().unwrap(), PreidForm::Full)
+                .unwrap();
+            decoder.decrypt(&preid).unwrap();
+        }
+        decoder.decrypt(PREID).unwrap();
+        assert_eq!(decoder.detected(), Some(PreidForm::Full));
+    }
+}
@@ -0,0 +1,187 @@
+///
+/// how the size of a list is spread over its folders, like `du`
+///
+use std::cmp::Reverse;
+
+use crate::parsers::{to_iec, Sha1Entity};
+
+/// rows of the in-chat table
+pub(crate) const DU_TABLE_ROWS: usize = 10;
+/// folder levels in the full report unless asked otherwise
+pub(crate) const DU_REPORT_DEPTH: usize = 3;
+
+struct FolderUsage<'a> {
+    name: &'a str,
+    files: u64,
+    size: u64,
+    /// largest first
+    children: Vec<FolderUsage<'a>>,
+}
+
+impl<'a> FolderUsage<'a> {
+    fn new(entity: &'a Sha1Entity) -> Self {
+        let mut children: Vec<FolderUsage> = entity.dirs.iter().map(FolderUsage::new).collect();
+        children.sort_by_key(|c| Reverse(c.size));
+        Self {
+            name: &entity.dir_name,
+            files: entity.files.len() as u64 + children.iter().map(|c| c.files).sum::<u64>(),
+            size: entity.files.iter().map(|f| f.size()).sum::<u64>()
+                + children.iter().map(|c| c.size).sum::<u64>(),
+            children,
+        }
+    }
+
+    /// down the single folder chain at the top, `a/b/c` when a and b hold nothing else
+    fn first_branch(&self) -> (String, &Self) {
+        let mut node = self;
+        let mut path = vec![self.name];
+        while node.children.len() == 1 && node.files == node.children[0].files {
+            node = &node.children[0];
+            path.push(node.name);
+        }
+        (path.join("/"), node)
+    }
+}
+
+fn share(size: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        size as f64 * 100.0 / total as f64
+    }
+}
+
+fn row(size: u64, total: u64, files: u64, name: &str) -> String {
+    format!(
+        "{:>7} {:>6.1}% {:>7}  {}\n",
+        to_iec(size),
+        share(size, total),
+        files,
+        name
+    )
+}
+
+const HEADER: &str = "   大小    占比  文件数  目录\n";
+
+/// the largest folders right below the root, for the chat
+pub(crate) fn du_table(entity: &Sha1Entity, rows: usize) -> String {
+    let root = FolderUsage::new(entity);
+    let (path, node) = root.first_branch();
+
+    let mut res = format!("{}\n{}", path, HEADER);
+    for child in node.children.iter().take(rows) {
+        res.push_str(&row(child.size, root.size, child.files, child.name));
+    }
+    if node.children.len() > rows {
+        let rest = &node.children[rows..];
+        let name = format!("其他 {} 个目录", rest.len());
+        res.push_str(&row(
+            rest.iter().map(|c| c.size).sum(),
+            root.size,
+            rest.iter().map(|c| c.files).sum(),
+            &name,
+        ));
+    }
+    let direct_files = node.files - node.children.iter().map(|c| c.files).sum::<u64>();
+    if direct_files != 0 {
+        let direct_size = node.size - node.children.iter().map(|c| c.size).sum::<u64>();
+        res.push_str(&row(
+            direct_size,
+            root.size,
+            direct_files,
+            "(当前目录下的文件)",
+        ));
+    }
+    res
+}
+
+fn push_report(res: &mut String, node: &FolderUsage, total: u64, depth: usize, max_depth: usize) {
+    let name = format!("{}{}", "  ".repeat(depth), node.name);
+    res.push_str(&row(node.size, total, node.files, &name));
+    if depth < max_depth {
+        for child in &node.children {
+            push_report(res, child, total, depth + 1, max_depth);
+        }
+    }
+}
+
+/// every folder down to `max_depth` levels below the root, largest first
+pub(crate) fn du_report(entity: &Sha1Entity, max_depth: usize) -> String {
+    let root = FolderUsage::new(entity);
+    let mut res = HEADER.to_owned();
+    push_report(&mut res, &root, root.size, 0, max_depth);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_parser::parse_lines;
+    use crate::parsers::entity_from_lines;
+
+    fn tree(files: &[(u64, &str)]) -> Sha1Entity {
+        let content: String = files
+            .iter()
+            .enumerate()
+            .map(|(i, (size, path))| {
+                format!("115://{}|{}|{:040X}|{:040X}|{}\n", i, size, i, i, path)
+            })
+            .collect();
+        entity_from_lines(parse_lines(&content).flatten()).unwrap()
+    }
+
+    #[test]
+    fn table_shares_count_loose_files() {
+        let entity = tree(&[
+            (500, "root|a"),
+            (100, "root|a|x"),
+            (300, "root|b"),
+            (100, ""),
+        ]);
+        let table = du_table(&entity, 1);
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(lines[0], "new_folder");
+        assert_eq!(lines[1], HEADER.trim_end());
+        assert_eq!(lines[2], row(900, 1000, 3, "root").trim_end());
+        assert_eq!(lines[3], row(100, 1000, 1, "(当前目录下的文件)").trim_end());
+        assert!(lines[2].contains(" 90.0% "));
+        assert!(lines[3].contains(" 10.0% "));
+
+        let below_root = tree(&[
+            (500, "root|a"),
+            (100, "root|a|x"),
+            (300, "root|b"),
+            (100, "root"),
+        ]);
+        let table = du_table(&below_root, 1);
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(lines[0], "root");
+        assert_eq!(lines[2], row(600, 1000, 2, "a").trim_end());
+        assert_eq!(lines[3], row(300, 1000, 1, "其他 1 个目录").trim_end());
+        assert_eq!(lines[4], row(100, 1000, 1, "(当前目录下的文件)").trim_end());
+    }
+
+    #[test]
+    fn table_follows_a_single_folder_chain() {
+        let entity = tree(&[(1, "root|a|x"), (3, "root|a|x|y")]);
+        let table = du_table(&entity, DU_TABLE_ROWS);
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(lines[0], "root/a/x");
+        assert!(lines[2].contains(" 75.0% ") && lines[2].ends_with("  y"));
+        assert!(lines[3].contains(" 25.0% "));
+    }
+
+    #[test]
+    fn report_stops_at_the_depth() {
+        let entity = tree(&[(1, "root|a|x"), (3, "root|b"), (0, "root|b|y")]);
+        let expected = [
+            HEADER.to_owned(),
+            row(4, 4, 3, "root"),
+            row(3, 4, 2, "  b"),
+            row(1, 4, 1, "  a"),
+        ];
+        assert_eq!(du_report(&entity, 1), expected.concat());
+        assert!(expected[1].contains("100.0%"));
+        assert_eq!(share(0, 0), 0.0);
+    }
+}
@@ -0,0 +1,287 @@
+///
+/// what kind of file a name is, judged by its extension
+///
+/// `a.tar.gz` and split volumes like `a.7z.001`, `a.part2.rar`, `a.z01` count as archives
+///
+use anyhow::bail;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::global::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum FileKind {
+    Video,
+    Audio,
+    Image,
+    Archive,
+    Document,
+    Subtitle,
+    Ebook,
+    Executable,
+    Other,
+}
+
+/// extension, kind and the inline search thumbnail if it has its own
+const EXTENSIONS: &[(&str, FileKind, Option<&str>)] = &[
+    ("mp4", FileKind::Video, Some(IMG_KIND_MP4)),
+    ("mkv", FileKind::Video, Some(IMG_KIND_MKV)),
+    ("wmv", FileKind::Video, Some(IMG_KIND_WMV)),
+    ("avi", FileKind::Video, None),
+    ("mov", FileKind::Video, None),
+    ("flv", FileKind::Video, None),
+    ("rmvb", FileKind::Video, None),
+    ("rm", FileKind::Video, None),
+    ("ts", FileKind::Video, None),
+    ("m2ts", FileKind::Video, None),
+    ("webm", FileKind::Video, None),
+    ("mpg", FileKind::Video, None),
+    ("mpeg", FileKind::Video, None),
+    ("m4v", FileKind::Video, None),
+    ("3gp", FileKind::Video, None),
+    ("vob", FileKind::Video, None),
+    ("iso", FileKind::Video, None),
+    ("mp3", FileKind::Audio, None),
+    ("flac", FileKind::Audio, None),
+    ("wav", FileKind::Audio, None),
+    ("ape", FileKind::Audio, None),
+    ("aac", FileKind::Audio, None),
+    ("m4a", FileKind::Audio, None),
+    ("ogg", FileKind::Audio, None),
+    ("wma", FileKind::Audio, None),
+    ("dsf", FileKind::Audio, None),
+    ("dff", FileKind::Audio, None),
+    ("opus", FileKind::Audio, None),
+    ("m3u", FileKind::Audio, Some(IMG_KIND_M3U)),
+    ("jpg", FileKind::Image, Some(IMG_KIND_JPG)),
+    ("jpeg", FileKind::Image, Some(IMG_KIND_JPG)),
+    ("png", FileKind::Image, Some(IMG_KIND_PNG)),
+    ("gif", FileKind::Image, Some(IMG_KIND_FILE_GIF)),
+    ("bmp", FileKind::Image, None),
+    ("webp", FileKind::Image, None),
+    ("tif", FileKind::Image, None),
+    ("tiff", FileKind::Image, None),
+    ("heic", FileKind::Image, None),
+    ("svg", FileKind::Image, None),
+    ("psd", FileKind::Image, None),
+    ("zip", FileKind::Archive, Some(IMG_KIND_ZIP)),
+    ("rar", FileKind::Archive, Some(IMG_KIND_RAR)),
+    ("7z", FileKind::Archive, Some(IMG_KIND_7Z)),
+    ("tar", FileKind::Archive, None),
+    ("gz", FileKind::Archive, None),
+    ("bz2", FileKind::Archive, None),
+    ("xz", FileKind::Archive, None),
+    ("tgz", FileKind::Archive, None),
+    ("zst", FileKind::Archive, None),
+    ("txt", FileKind::Document, Some(IMG_KIND_TXT)),
+    ("doc", FileKind::Document, Some(IMG_KIND_DOC)),
+    ("docx", FileKind::Document, Some(IMG_KIND_DOCX)),
+    ("xls", FileKind::Document, Some(IMG_KIND_XLS)),
+    ("xlsx", FileKind::Document, Some(IMG_KIND_XLSX)),
+    ("pdf", FileKind::Document, Some(IMG_KIND_PDF)),
+    ("csv", FileKind::Document, Some(IMG_KIND_CSV)),
+    ("json", FileKind::Document, Some(IMG_KIND_JSON)),
+    ("html", FileKind::Document, Some(IMG_KIND_HTML)),
+    ("yaml", FileKind::Document, Some(IMG_KIND_YAML)),
+    ("js", FileKind::Document, Some(IMG_KIND_JS)),
+    ("ppt", FileKind::Document, None),
+    ("pptx", FileKind::Document, None),
+    ("md", FileKind::Document, None),
+    ("htm", FileKind::Document, None),
+    ("nfo", FileKind::Document, None),
+    ("srt", FileKind::Subtitle, Some(IMG_KIND_SRT)),
+    ("ass", FileKind::Subtitle, Some(IMG_KIND_ASS)),
+    ("ssa", FileKind::Subtitle, Some(IMG_KIND_SSA)),
+    ("sub", FileKind::Subtitle, None),
+    ("idx", FileKind::Subtitle, None),
+    ("vtt", FileKind::Subtitle, None),
+    ("sup", FileKind::Subtitle, None),
+    ("epub", FileKind::Ebook, Some(IMG_KIND_EPUB)),
+    ("mobi", FileKind::Ebook, Some(IMG_KIND_MOBI)),
+    ("azw3", FileKind::Ebook, Some(IMG_KIND_AZW3)),
+    ("azw", FileKind::Ebook, None),
+    ("djvu", FileKind::Ebook, None),
+    ("fb2", FileKind::Ebook, None),
+    ("exe", FileKind::Executable, Some(IMG_KIND_EXE)),
+    ("apk", FileKind::Executable, Some(IMG_KIND_APK)),
+    ("bat", FileKind::Executable, Some(IMG_KIND_BAT)),
+    ("crx", FileKind::Executable, Some(IMG_KIND_CRX)),
+    ("msi", FileKind::Executable, None),
+    ("dmg", FileKind::Executable, None),
+    ("pkg", FileKind::Executable, None),
+    ("deb", FileKind::Executable, None),
+    ("rpm", FileKind::Executable, None),
+    ("sh", FileKind::Executable, None),
+    ("torrent", FileKind::Other, Some(IMG_KIND_TORRENT)),
+    ("db", FileKind::Other, Some(IMG_KIND_DB)),
+    ("eof", FileKind::Other, Some(IMG_KIND_EOF)),
+];
+
+lazy_static! {
+    static ref EXTENSION_TABLE: HashMap<&'static str, (FileKind, Option<&'static str>)> =
+        EXTENSIONS
+            .iter()
+            .map(|(ext, kind, thumb)| (*ext, (*kind, *thumb)))
+            .collect();
+}
+
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+}
+
+/// `r00` `z01` style volumes of rar and zip
+fn is_volume(ext: &str) -> bool {
+    matches!(ext.as_bytes().first(), Some(b'r' | b'z')) && ext.len() == 3 && is_digits(&ext[1..])
+}
+
+/// `part2` of `a.part2.rar`
+fn is_part(ext: &str) -> bool {
+    ext.strip_prefix("part").map(is_digits).unwrap_or(false)
+}
+
+/// lowercase, `tar.gz` and `7z.001` are kept whole, empty when there is none
+pub(crate) fn full_extension(name: &str) -> String {
+    let name = name.to_lowercase();
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, ext),
+        _ => return String::new(),
+    };
+    match stem.rsplit_once('.') {
+        Some((inner, prev)) if !inner.is_empty() => {
+            if (prev == "tar" && EXTENSION_TABLE.contains_key(ext)) || is_digits(ext) {
+                format!("{}.{}", prev, ext)
+            } else {
+                ext.to_owned()
+            }
+        }
+        _ => ext.to_owned(),
+    }
+}
+
+/// the part of an extension that tells the kind, `7z` of `7z.001`, `gz` of `tar.gz`
+fn main_extension(ext: &str) -> &str {
+    match ext.split_once('.') {
+        Some(("tar", _)) => "tar",
+        Some((main, _)) => main,
+        None => ext,
+    }
+}
+
+impl FileKind {
+    /// `ext` is lowercase and without the dot
+    pub(crate) fn from_extension(ext: &str) -> Self {
+        if let Some((kind, _)) = EXTENSION_TABLE.get(main_extension(ext)) {
+            return *kind;
+        }
+        let last = ext.rsplit('.').next().unwrap_or_default();
+        if is_volume(last) || is_part(last) {
+            FileKind::Archive
+        } else {
+            FileKind::Other
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Self {
+        let ext = full_extension(name);
+        // `a.part2.rar` and the self extracting `a.part1.exe` only show their last extension
+        if matches!(ext.as_str(), "rar" | "exe") {
+            let stem = &name[..name.len() - ext.len() - 1];
+            if let Some((_, prev)) = stem.rsplit_once('.') {
+                if is_part(&prev.to_lowercase()) {
+                    return FileKind::Archive;
+                }
+            }
+        }
+        FileKind::from_extension(&ext)
+    }
+
+    pub(crate) const ALL: [FileKind; 9] = [
+        FileKind::Video,
+        FileKind::Audio,
+        FileKind::Image,
+        FileKind::Archive,
+        FileKind::Document,
+        FileKind::Subtitle,
+        FileKind::Ebook,
+        FileKind::Executable,
+        FileKind::Other,
+    ];
+
+    fn code(&self) -> &'static str {
+        match self {
+            FileKind::Video => "video",
+            FileKind::Audio => "audio",
+            FileKind::Image => "image",
+            FileKind::Archive => "archive",
+            FileKind::Document => "document",
+            FileKind::Subtitle => "subtitle",
+            FileKind::Ebook => "ebook",
+            FileKind::Executable => "executable",
+            FileKind::Other => "other",
+        }
+    }
+}
+
+/// the inline search thumbnail for a file name, `None` when the extension has none of its own
+pub(crate) fn thumbnail(name: &str) -> Option<&'static str> {
+    let ext = full_extension(name);
+    if let Some((_, Some(thumb))) = EXTENSION_TABLE.get(main_extension(&ext)) {
+        return Some(thumb);
+    }
+    match FileKind::from_extension(&ext) {
+        FileKind::Video => Some(IMG_KIND_FILE_VIDEO),
+        FileKind::Audio => Some(IMG_KIND_FILE_MUSIC),
+        FileKind::Image => Some(IMG_KIND_FILE_PICTURE),
+        _ => None,
+    }
+}
+
+/// `video` or `视频`
+impl FromStr for FileKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        match FileKind::ALL
+            .iter()
+            .find(|kind| kind.code() == s || kind.to_string() == s)
+        {
+            Some(kind) => Ok(*kind),
+            None => bail!("unknown file type: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for FileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FileKind::Video => "视频",
+            FileKind::Audio => "音频",
+            FileKind::Image => "图片",
+            FileKind::Archive => "压缩包",
+            FileKind::Document => "文档",
+            FileKind::Subtitle => "字幕",
+            FileKind::Ebook => "电子书",
+            FileKind::Executable => "程序",
+            FileKind::Other => "其他",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_volumes_are_archives() {
+        assert_eq!(FileKind::from_name("a.part2.rar"), FileKind::Archive);
+        assert_eq!(FileKind::from_name("a.PART1.exe"), FileKind::Archive);
+        assert_eq!(FileKind::from_name("a.7z.001"), FileKind::Archive);
+        assert_eq!(FileKind::from_name("a.z01"), FileKind::Archive);
+        assert_eq!(FileKind::from_name("setup.exe"), FileKind::Executable);
+        assert_eq!(FileKind::from_name("movie.part1.mkv"), FileKind::Video);
+    }
+}
@@ -0,0 +1,268 @@
+///
+/// picking files out of a list
+///
+/// conditions are separated by spaces, all of them have to match:
+/// `ext:mkv,mp4` `type:video,字幕` `size:>1G` `size:<=2G` `size:1G-10G` `name:<regex>` `path:<regex>`
+/// `dir:a/b`, `>` and `<` are strict, `>=`, `<=` and ranges include their bounds,
+/// anything else is looked up in the file name, case insensitive
+///
+use anyhow::{anyhow, bail, Result};
+use regex::{Regex, RegexBuilder};
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+use std::str::FromStr;
+
+use crate::file_kind::{full_extension, FileKind};
+use crate::line_parser::Sha1Line;
+use crate::parsers::{parse_size, to_iec};
+
+#[derive(Debug, Default)]
+pub(crate) struct Filter {
+    extensions: Option<HashSet<String>>,
+    kinds: Option<HashSet<FileKind>>,
+    min_size: Option<Bound<u64>>,
+    max_size: Option<Bound<u64>>,
+    name: Option<Regex>,
+    path: Option<Regex>,
+    folder_prefix: Option<Vec<String>>,
+    keywords: Vec<String>,
+}
+
+fn regex(pattern: &str) -> Result<Regex> {
+    Ok(RegexBuilder::new(pattern).case_insensitive(true).build()?)
+}
+
+fn size(s: &str) -> Result<u64> {
+    parse_size(s).ok_or_else(|| anyhow!("invalid size: {}", s))
+}
+
+impl FromStr for Filter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = Filter::default();
+        for term in s.split_whitespace() {
+            let (key, value) = term.split_once(':').unwrap_or(("", term));
+            match key {
+                "ext" => {
+                    let extensions = value
+                        .split(',')
+                        .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                        .filter(|ext| !ext.is_empty());
+                    filter
+                        .extensions
+                        .get_or_insert_with(HashSet::new)
+                        .extend(extensions);
+                }
+                "type" | "类型" => {
+                    let kinds = value
+                        .split([',', '，'])
+                        .filter(|kind| !kind.is_empty())
+                        .map(FileKind::from_str)
+                        .collect::<Result<Vec<_>>>()?;
+                    filter.kinds.get_or_insert_with(HashSet::new).extend(kinds);
+                }
+                "size" => {
+                    if let Some(min) = value.strip_prefix(">=") {
+                        filter.min_size = Some(Bound::Included(size(min)?));
+                    } else if let Some(min) = value.strip_prefix('>') {
+                        filter.min_size = Some(Bound::Excluded(size(min)?));
+                    } else if let Some(max) = value.strip_prefix("<=") {
+                        filter.max_size = Some(Bound::Included(size(max)?));
+                    } else if let Some(max) = value.strip_prefix('<') {
+                        filter.max_size = Some(Bound::Excluded(size(max)?));
+                    } else if let Some((min, max)) = value.split_once('-') {
+                        // either end may be left open, `1G-` or `-1G`
+                        if !min.is_empty() {
+                            filter.min_size = Some(Bound::Included(size(min)?));
+                        }
+                        if !max.is_empty() {
+                            filter.max_size = Some(Bound::Included(size(max)?));
+                        }
+                    } else {
+                        bail!("invalid size range: {}", value);
+                    }
+                }
+                "name" => filter.name = Some(regex(value)?),
+                "path" => filter.path = Some(regex(value)?),
+                "dir" => {
+                    filter.folder_prefix = Some(
+                        value
+                            .split('/')
+                            .filter(|s| !s.is_empty())
+                            .map(str::to_owned)
+                            .collect(),
+                    )
+                }
+                _ => filter.keywords.push(term.to_lowercase()),
+            }
+        }
+        if filter.is_empty() {
+            bail!("empty filter");
+        }
+        Ok(filter)
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut conditions = Vec::new();
+        if let Some(extensions) = &self.extensions {
+            let mut extensions: Vec<&str> = extensions.iter().map(String::as_str).collect();
+            extensions.sort_unstable();
+            conditions.push(format!("扩展名 {}", extensions.join(",")));
+        }
+        if let Some(kinds) = &self.kinds {
+            let mut kinds: Vec<&FileKind> = kinds.iter().collect();
+            kinds.sort_unstable();
+            let kinds: Vec<String> = kinds.iter().map(|kind| kind.to_string()).collect();
+            conditions.push(format!("类型 {}", kinds.join(",")));
+        }
+        match (self.min_size, self.max_size) {
+            (Some(Bound::Included(min)), Some(Bound::Included(max))) => {
+                conditions.push(format!("大小 {} - {}", to_iec(min), to_iec(max)))
+            }
+            (min, max) => {
+                let bounds = [(min, ">=", ">"), (max, "<=", "<")];
+                for (bound, inclusive, strict) in bounds {
+                    match bound {
+                        Some(Bound::Included(size)) => {
+                            conditions.push(format!("大小 {} {}", inclusive, to_iec(size)))
+                        }
+                        Some(Bound::Excluded(size)) => {
+                            conditions.push(format!("大小 {} {}", strict, to_iec(size)))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        if let Some(name) = &self.name {
+            conditions.push(format!("文件名匹配 {}", name));
+        }
+        if let Some(path) = &self.path {
+            conditions.push(format!("路径匹配 {}", path));
+        }
+        if let Some(prefix) = &self.folder_prefix {
+            conditions.push(format!("目录 {}", prefix.join("/")));
+        }
+        if !self.keywords.is_empty() {
+            conditions.push(format!("文件名包含 {}", self.keywords.join(" ")));
+        }
+        write!(f, "{}", conditions.join(", "))
+    }
+}
+
+impl Filter {
+    fn is_empty(&self) -> bool {
+        self.extensions.is_none()
+            && self.kinds.is_none()
+            && self.min_size.is_none()
+            && self.max_size.is_none()
+            && self.name.is_none()
+            && self.path.is_none()
+            && self.folder_prefix.is_none()
+            && self.keywords.is_empty()
+    }
+
+    pub(crate) fn matches(&self, record: &Sha1Line) -> bool {
+        let file = &record.file;
+        if let Some(extensions) = &self.extensions {
+            // `ext:gz` and `ext:tar.gz` both take `a.tar.gz`
+            if !extensions.contains(&file.extension())
+                && !extensions.contains(&full_extension(file.name()))
+            {
+                return false;
+            }
+        }
+        if let Some(kinds) = &self.kinds {
+            if !kinds.contains(&file.kind()) {
+                return false;
+            }
+        }
+        let size_range = (
+            self.min_size.unwrap_or(Bound::Unbounded),
+            self.max_size.unwrap_or(Bound::Unbounded),
+        );
+        if !size_range.contains(&file.size()) {
+            return false;
+        }
+        if let Some(name) = &self.name {
+            if !name.is_match(file.name()) {
+                return false;
+            }
+        }
+        if let Some(path) = &self.path {
+            let full_path = [record.folders.join("/"), file.name().to_owned()].join("/");
+            if !path.is_match(&full_path) {
+                return false;
+            }
+        }
+        if let Some(prefix) = &self.folder_prefix {
+            if !record.folders.starts_with(prefix) {
+                return false;
+            }
+        }
+        let name = file.name().to_lowercase();
+        self.keywords.iter().all(|keyword| name.contains(keyword))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_parser::parse_line;
+
+    const HASH: &str = "0123456789ABCDEF0123456789ABCDEF01234567";
+
+    fn record(name: &str, size: u64, folders: &str) -> Sha1Line {
+        let line = format!("115://{}|{}|{}|{}{}", name, size, HASH, HASH, folders);
+        parse_line(&line, 1).unwrap().unwrap()
+    }
+
+    fn matches(filter: &str, record: &Sha1Line) -> bool {
+        filter.parse::<Filter>().unwrap().matches(record)
+    }
+
+    #[test]
+    fn strict_and_inclusive_sizes() {
+        let file = record("a.mkv", 1024, "");
+        assert!(!matches("size:>1K", &file));
+        assert!(matches("size:>=1K", &file));
+        assert!(!matches("size:<1K", &file));
+        assert!(matches("size:<=1K", &file));
+        assert!(matches("size:1K-2K", &file));
+        assert!(matches("size:-1K", &file));
+        assert!(!matches("size:1025-", &file));
+    }
+
+    #[test]
+    fn parses_every_condition() {
+        let file = record("Movie.Part1.MKV", 10, "|影视|电影");
+        assert!(matches("ext:mkv,mp4", &file));
+        assert!(!matches("ext:mp4", &file));
+        assert!(matches("type:视频", &file));
+        assert!(matches("name:^movie", &file));
+        assert!(matches("path:电影/movie", &file));
+        assert!(matches("dir:影视", &file));
+        assert!(!matches("dir:电影", &file));
+        assert!(matches("part1 ext:mkv", &file));
+        assert!(!matches("part2", &file));
+    }
+
+    #[test]
+    fn rejects_bad_filters() {
+        assert!("".parse::<Filter>().is_err());
+        assert!("size:abc".parse::<Filter>().is_err());
+        assert!("size:1G".parse::<Filter>().is_err());
+        assert!("type:不存在".parse::<Filter>().is_err());
+        assert!("name:(".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn describes_bounds() {
+        let filter: Filter = "size:>1K size:<=2K".parse().unwrap();
+        assert_eq!(filter.to_string(), "大小 > 1.0k, 大小 <= 2.0k");
+    }
+}
@@ -3,14 +3,36 @@ use teloxide::adaptors::{AutoSend, Throttle};
 pub(crate) type Bot = AutoSend<Throttle<teloxide::Bot>>;
 
 pub(crate) const ROOT_FOLDER: &str = ".cache/tgtmp/";
+pub(crate) const TORRENT_CACHE_FOLDER: &str = ".cache/torrents/";
 pub(crate) static mut DEBUG_CC_ID: i64 = -1;
+/// the public bot api hands out files up to this size
+pub(crate) const PUBLIC_API_MAX_FILE_SIZE: u64 = 1024 * 1024 * 20;
+/// a local mode bot api server hands out files up to this size
+pub(crate) const LOCAL_API_MAX_FILE_SIZE: u64 = 1024 * 1024 * 2000;
+/// documents larger than this are ignored
+pub(crate) static mut MAX_FILE_SIZE: u64 = PUBLIC_API_MAX_FILE_SIZE;
+/// the bot api server runs with `--local` and `get_file` returns a path on this machine
+pub(crate) static mut LOCAL_BOT_API: bool = false;
+/// txt to json drops empty folders and lines without folders and puts several roots under a
+/// synthetic parent, like it used to
+pub(crate) static mut LEGACY_ROUND_TRIP: bool = false;
 pub(crate) const HELP: &str = r"使用方法: 向机器人发送 sha1 文件, 出现对应选项。
 FAQ:
 1. json 文件需要以 .json 文件名后缀结尾，否则忽略。
-2. 仅含有目录信息的 txt 才支持转换成 json 文件。
+2. txt 转 json 再转回 txt 会保留多个根目录、没有目录的链接、链接顺序、sha1 大小写和链接末尾的附加字段 (json 里多出 line_numbers 记录顺序, 大小写混杂的 sha1 会转成大写); 空目录只在选择「转成TXT(保留空目录)」时写成 ||||目录 行, 其他工具可能不认。
 3. 目前去重和除错功能仅对 txt 格式的 115sha1 文件有效, 请需要进行相应操作用 line 格式的 txt 文件
-4. 目前仅支持20M内的文件。
-5. 有问题群里@我
+4. 目前仅支持{max_file_size}内的文件。
+5. 导出的 csv/tsv 表格修改后发回来即可转回 115 链接, 需保留 name,size,sha1,block_sha1,folder 这几列的表头。
+6. 回复列表发送 'set add 加入 (最多 10 个, 最后一次加入后保留 1 小时), 再回复最后一个列表发送 'set 并集/交集/差集/对称差 (union/inter/diff/xor) 进行集合运算, 两个列表时直接回复即可。
+7. 回复列表发送 'split 1000 (按链接数), 'split 2TB (按总大小) 或 'split 目录 (按目录) 拆分列表。
+8. 回复列表发送 'filter 条件 筛选文件, 条件用空格分隔: ext:mkv,mp4 type:视频,字幕 size:>1G size:<=2G size:1G-10G (范围包含两端) name:正则 path:正则 dir:目录/子目录, 其他文字按文件名搜索。
+9. 回复列表发送 'tree 操作 调整目录结构, 多个操作用逗号分隔, 如 'tree strip 1, prune, sort size。支持 rename 新名字, strip N, flatten, ext, type, prune, sort name/size。
+10. 回复列表发送 'du 查看各目录大小, 'du 5 file 获取 5 层目录的完整报告。
+11. 回复列表发送 'dup 查找内容相同但改了名的文件和同名不同内容的文件, 'dup 最早/最后/最大/最浅 每组只保留一个。
+12. 回复列表发送 'repair 自动修复全角分隔符、缺少或重复的 115://、多余空格、零宽字符、连在一起的链接等格式问题。
+13. 支持 zip、gz、tar.gz 压缩包，会统计里面每个 txt、json、csv 列表并给出合计，私聊可以合并去重成一个列表。
+14. 加密的列表发来会自动解密, 省略末尾块和完整 base64 两种 preid 都能识别, 无法解密的行另附失败报告; 私聊发送普通列表可以把块 sha1 加密回 preid。
+15. 有问题群里@我
 
 更多详细内容：https://telegra.ph/het-12-01";
 
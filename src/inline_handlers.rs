@@ -1,3 +1,4 @@
+use crate::file_kind::thumbnail;
 use crate::global::Bot;
 use crate::global::*;
 use crate::search::Librarian;
@@ -98,44 +99,8 @@ pub(crate) async fn inline_query_handler(
         };
 
         thumb_url = if let Some(filename) = &record.filename {
-            if let Some(parts) = filename.rsplit_once(".") {
-                match parts.1.to_uppercase().as_str() {
-                    "7Z" => IMG_KIND_7Z,
-                    "APK" => IMG_KIND_APK,
-                    "ASS" => IMG_KIND_ASS,
-                    "BAT" => IMG_KIND_BAT,
-                    "CRX" => IMG_KIND_CRX,
-                    "CSV" => IMG_KIND_CSV,
-                    "DB" => IMG_KIND_DB,
-                    "DOC" => IMG_KIND_DOC,
-                    "DOCX" => IMG_KIND_DOCX,
-                    "EOF" => IMG_KIND_EOF,
-                    "EPUB" => IMG_KIND_EPUB,
-                    "EXE" => IMG_KIND_EXE,
-                    "GIF" => IMG_KIND_FILE_GIF,
-                    "HTML" => IMG_KIND_HTML,
-                    "JPG" => IMG_KIND_JPG,
-                    "JS" => IMG_KIND_JS,
-                    "JSON" => IMG_KIND_JSON,
-                    "M3U" => IMG_KIND_M3U,
-                    "MKV" => IMG_KIND_MKV,
-                    "MOBI" => IMG_KIND_MOBI,
-                    "MP4" => IMG_KIND_MP4,
-                    "PDF" => IMG_KIND_PDF,
-                    "PNG" => IMG_KIND_PNG,
-                    "RAR" => IMG_KIND_RAR,
-                    "SRT" => IMG_KIND_SRT,
-                    "SSA" => IMG_KIND_SSA,
-                    "TORRENT" => IMG_KIND_TORRENT,
-                    "TXT" => IMG_KIND_TXT,
-                    "WMV" => IMG_KIND_WMV,
-                    "XLS" => IMG_KIND_XLS,
-                    "XLSX" => IMG_KIND_XLSX,
-                    "YAML" => IMG_KIND_YAML,
-                    "ZIP" => IMG_KIND_ZIP,
-                    "AZW3" => IMG_KIND_AZW3,
-                    _ => thumb_url,
-                }
+            if filename.contains('.') {
+                thumbnail(filename).unwrap_or(thumb_url)
             } else {
                 // does not have ext
                 IMG_KIND_FILE_OTHERS
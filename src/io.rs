@@ -1,43 +1,144 @@
 use anyhow::{bail, Context, Result};
+use encoding_rs::{UTF_16BE, UTF_16LE, UTF_8};
+use std::borrow::Cow;
+use std::fmt;
 use std::{io::SeekFrom, path::Path};
 use tokio::{
     fs::File as TokioFile,
-    io::{AsyncReadExt, AsyncSeekExt, BufReader},
+    io::{AsyncBufRead, AsyncReadExt, AsyncSeekExt, BufReader},
 };
 
-pub(crate) fn has_bom(path: &Path) -> Result<bool> {
-    let file =
-        std::fs::File::open(path).context(format!("failed to open {}", path.to_string_lossy()))?;
-    let reader = std::io::BufReader::new(file);
-    let mut buffer = [0; 3];
-    let mut content = std::io::Read::take(reader, 3);
-    let num_reads =
-        std::io::Read::read(&mut content, &mut buffer).context("fail to read bytes from file")?;
-    Ok(num_reads < 3 || buffer == [0xef, 0xbb, 0xbf])
+use crate::text_encoding;
+
+/// bytes looked at to tell the encoding of a file
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// byte order marks, the utf32 ones are checked first as utf16le is a prefix of utf32le
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Bom {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Utf32Le,
+    Utf32Be,
+}
+
+const BOMS: [(Bom, &[u8]); 5] = [
+    (Bom::Utf32Le, &[0xff, 0xfe, 0x00, 0x00]),
+    (Bom::Utf32Be, &[0x00, 0x00, 0xfe, 0xff]),
+    (Bom::Utf8, &[0xef, 0xbb, 0xbf]),
+    (Bom::Utf16Le, &[0xff, 0xfe]),
+    (Bom::Utf16Be, &[0xfe, 0xff]),
+];
+
+impl Bom {
+    /// the mark the bytes start with, a file shorter than a mark has none
+    pub(crate) fn sniff(bytes: &[u8]) -> Option<Bom> {
+        BOMS.iter()
+            .find(|(_, mark)| bytes.starts_with(mark))
+            .map(|(bom, _)| *bom)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Bom::Utf8 => 3,
+            Bom::Utf16Le | Bom::Utf16Be => 2,
+            Bom::Utf32Le | Bom::Utf32Be => 4,
+        }
+    }
+
+    /// `bytes` come after the mark, broken sequences become U+FFFD
+    pub(crate) fn decode<'a>(&self, bytes: &'a [u8]) -> Cow<'a, str> {
+        let encoding = match self {
+            Bom::Utf8 => UTF_8,
+            Bom::Utf16Le => UTF_16LE,
+            Bom::Utf16Be => UTF_16BE,
+            Bom::Utf32Le => return Cow::Owned(decode_utf32(bytes, u32::from_le_bytes)),
+            Bom::Utf32Be => return Cow::Owned(decode_utf32(bytes, u32::from_be_bytes)),
+        };
+        encoding.decode_without_bom_handling(bytes).0
+    }
+}
+
+impl fmt::Display for Bom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Bom::Utf8 => "UTF-8",
+            Bom::Utf16Le => "UTF-16LE",
+            Bom::Utf16Be => "UTF-16BE",
+            Bom::Utf32Le => "UTF-32LE",
+            Bom::Utf32Be => "UTF-32BE",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// encoding_rs leaves utf32 out
+fn decode_utf32(bytes: &[u8], from_bytes: fn([u8; 4]) -> u32) -> String {
+    bytes
+        .chunks(4)
+        .map(|chunk| {
+            <[u8; 4]>::try_from(chunk)
+                .ok()
+                .and_then(|unit| char::from_u32(from_bytes(unit)))
+                .unwrap_or(char::REPLACEMENT_CHARACTER)
+        })
+        .collect()
+}
+
+/// the whole of a file as utf8, bom removed, the same for the sync and async readers
+fn into_utf8(mut bytes: Vec<u8>) -> String {
+    let detected = text_encoding::detect(&bytes);
+    if !detected.is_utf8() {
+        return text_encoding::decode(&bytes).0.into_owned();
+    }
+    // no copy for the common case
+    bytes.drain(..detected.bom_len());
+    match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    }
+}
+
+pub(crate) fn read_utf8_sync(input: &Path) -> Result<String> {
+    let bytes =
+        std::fs::read(input).context(format!("failed to open {}", input.to_string_lossy()))?;
+    Ok(into_utf8(bytes))
 }
 
-pub(crate) async fn has_bom_async(path: &Path) -> Result<bool> {
-    let file = TokioFile::open(path)
+/// the whole file as utf8 text, whatever it was written in
+pub(crate) async fn read_utf8(input: &Path) -> Result<String> {
+    let bytes = tokio::fs::read(input)
         .await
-        .context(format!("failed to open {}", path.to_string_lossy()))?;
-    let reader = BufReader::new(file);
-    let mut buffer = [0; 3];
-    let mut content = reader.take(3);
-    let num_reads = content
-        .read(&mut buffer)
+        .context(format!("failed to open {}", input.to_string_lossy()))?;
+    Ok(into_utf8(bytes))
+}
+
+/// the start of a file and whether there is more after it
+pub(crate) async fn read_sample(file: &mut TokioFile) -> Result<(Vec<u8>, bool)> {
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    file.take(SAMPLE_SIZE as u64 + 1)
+        .read_to_end(&mut sample)
         .await
         .context("fail to read bytes from file")?;
-    Ok(num_reads < 3 || buffer == [0xef, 0xbb, 0xbf])
+    let truncated = sample.len() > SAMPLE_SIZE;
+    sample.truncate(SAMPLE_SIZE);
+    Ok((sample, truncated))
 }
 
-pub(crate) async fn open_without_bom(input: &Path) -> Result<TokioFile> {
+/// a buffered utf8 reader over the file, streaming when the file already is utf8
+pub(crate) async fn open_utf8(input: &Path) -> Result<Box<dyn AsyncBufRead + Unpin + Send>> {
     let mut file = TokioFile::open(input)
         .await
         .context(format!("failed to open {}", input.to_string_lossy()))?;
-    if has_bom_async(input).await? {
-        file.seek(SeekFrom::Start(3)).await?;
+    let detected = text_encoding::detect_file(&mut file).await?;
+    if detected.is_utf8() {
+        file.seek(SeekFrom::Start(detected.bom_len() as u64))
+            .await?;
+        return Ok(Box::new(BufReader::new(file)));
     }
-    Ok(file)
+    let text = read_utf8(input).await?;
+    Ok(Box::new(std::io::Cursor::new(text.into_bytes())))
 }
 
 pub(crate) async fn check_input(input: &Path) -> Result<()> {
@@ -1,10 +1,38 @@
+pub(crate) mod archive;
 pub(crate) mod callback_handlers;
 pub(crate) mod commands;
+pub(crate) mod conflicts;
 pub(crate) mod decryption;
+pub(crate) mod du;
+pub(crate) mod file_kind;
+pub(crate) mod filter;
 pub(crate) mod global;
 pub(crate) mod io;
+pub(crate) mod line_parser;
+pub(crate) mod lint;
+pub(crate) mod list_ops;
+pub(crate) mod magnet;
 pub(crate) mod message_handlers;
 pub(crate) mod parsers;
+pub(crate) mod providers;
+pub(crate) mod repair;
 pub(crate) mod search;
+pub(crate) mod sha1_csv;
+pub(crate) mod sha1_json;
+pub(crate) mod split;
+pub(crate) mod text_encoding;
+pub(crate) mod torrent;
+pub(crate) mod tree_ops;
 pub(crate) mod inline_handlers;
 pub mod app;
+
+/// entry points for `benches/`
+#[doc(hidden)]
+pub mod bench {
+    use std::path::Path;
+
+    /// `preserve` as the bot passes it unless `LEGACY_ROUND_TRIP` is set
+    pub async fn line2json(input: &Path, output: &Path, preserve: bool) -> anyhow::Result<()> {
+        crate::parsers::line2json(input, output, preserve).await
+    }
+}
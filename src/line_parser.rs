@@ -0,0 +1,343 @@
+///
+/// one streaming parser for 115 sha1 link lines, every consumer goes through here
+///
+/// `115://name|size|sha1|block_sha1|folder|subfolder...`
+///
+/// an empty field ends the folder path, whatever follows is kept as is, `||||folder|subfolder`
+/// stands for an empty folder
+///
+use anyhow::{Context, Result};
+use std::fmt;
+use std::path::Path;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, Lines};
+
+use crate::decryption::is_preid;
+use crate::io::{check_input, open_utf8};
+use crate::parsers::FileRepr;
+
+/// a line that parsed into a file
+#[derive(Debug)]
+pub(crate) struct Sha1Line {
+    pub(crate) line_number: usize,
+    pub(crate) file: FileRepr,
+    /// the fields after the block sha1 up to an empty one, normally the folder path
+    pub(crate) folders: Vec<String>,
+    /// the block sha1 is still an encrypted preid
+    pub(crate) encrypted: bool,
+}
+
+impl Sha1Line {
+    /// back to `115://name|size|sha1|block_sha1|folder...`
+    pub(crate) fn to_line(&self) -> String {
+        let mut line = self.file.to_sha1_link();
+        for folder in &self.folders {
+            line.push('|');
+            line.push_str(folder);
+        }
+        if !self.file.extra().is_empty() {
+            line.push_str("||");
+            line.push_str(&self.file.extra().join("|"));
+        }
+        line
+    }
+
+    /// every field after the block sha1, as in the line
+    pub(crate) fn trailing_fields(&self) -> Vec<&str> {
+        let mut fields: Vec<&str> = self.folders.iter().map(String::as_str).collect();
+        if !self.file.extra().is_empty() {
+            fields.push("");
+            fields.extend(self.file.extra().iter().map(String::as_str));
+        }
+        fields
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum LineError {
+    TooFewFields(usize),
+    EmptyName,
+    BadSize(String),
+    BadSha1Length(usize),
+    BadSha1Hex,
+    MissingBlockHash,
+    BadBlockHashLength(usize),
+    BadBlockHash,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineError::TooFewFields(n) => write!(f, "字段不足, 需要至少 4 个, 只有 {} 个", n),
+            LineError::EmptyName => write!(f, "文件名为空"),
+            LineError::BadSize(size) => write!(f, "文件大小不是有效数字: {}", size),
+            LineError::BadSha1Length(n) => write!(f, "sha1 长度应为 40, 实际为 {}", n),
+            LineError::BadSha1Hex => write!(f, "sha1 含有非十六进制字符"),
+            LineError::MissingBlockHash => write!(f, "缺少块 sha1"),
+            LineError::BadBlockHashLength(n) => write!(f, "块 sha1 长度应为 40, 实际为 {}", n),
+            LineError::BadBlockHash => write!(f, "块 sha1 既不是 40 位十六进制也不是加密的 preid"),
+        }
+    }
+}
+
+impl std::error::Error for LineError {}
+
+/// a line that did not parse, kept around for the error report
+#[derive(Debug)]
+pub(crate) struct RejectedLine {
+    pub(crate) line_number: usize,
+    pub(crate) line: String,
+    pub(crate) reason: LineError,
+}
+
+impl fmt::Display for RejectedLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "第 {} 行: {}\t{}",
+            self.line_number, self.reason, self.line
+        )
+    }
+}
+
+pub(crate) type ParsedLine = std::result::Result<Sha1Line, RejectedLine>;
+
+/// what a non blank line holds
+pub(crate) enum LineItem {
+    File(ParsedLine),
+    /// the path of an empty folder
+    Folder(Vec<String>),
+}
+
+/// starts a line that only holds the path of an empty folder
+pub(crate) const FOLDER_LINE_PREFIX: &str = "||||";
+
+/// the path of an empty folder line, `None` for any other line
+pub(crate) fn parse_folder_line(line: &str) -> Option<Vec<String>> {
+    let path = line.strip_prefix(FOLDER_LINE_PREFIX)?;
+    if path.is_empty() {
+        return None;
+    }
+    Some(path.split('|').map(str::to_owned).collect())
+}
+
+/// parse a single line, `Ok(None)` for blank lines
+pub(crate) fn parse_line(line: &str, line_number: usize) -> Result<Option<Sha1Line>, LineError> {
+    if line.chars().all(|c| c.is_ascii_whitespace()) {
+        return Ok(None);
+    }
+
+    let parts: Vec<&str> = line.split('|').collect();
+    if parts.len() < 4 {
+        return Err(LineError::TooFewFields(parts.len()));
+    }
+
+    let name = parts[0].strip_prefix("115://").unwrap_or(parts[0]);
+    if name.is_empty() {
+        return Err(LineError::EmptyName);
+    }
+    let size = parts[1]
+        .parse()
+        .map_err(|_| LineError::BadSize(parts[1].to_owned()))?;
+
+    let sha1 = parts[2];
+    if sha1.len() != 40 {
+        return Err(LineError::BadSha1Length(sha1.len()));
+    }
+    let sha1 = sha1.parse().map_err(|_| LineError::BadSha1Hex)?;
+
+    let block = parts[3];
+    if block.is_empty() || block == "0" || block == "error" {
+        return Err(LineError::MissingBlockHash);
+    }
+    // anything purely hex is meant to be a plain block sha1, the rest has to be shaped like a preid
+    let encrypted = !block.chars().all(|c| c.is_ascii_hexdigit());
+    if !encrypted && block.len() != 40 {
+        return Err(LineError::BadBlockHashLength(block.len()));
+    }
+    if encrypted && !is_preid(block) {
+        return Err(LineError::BadBlockHash);
+    }
+
+    let rest = &parts[4..];
+    let (folders, extra) = match rest.iter().position(|s| s.is_empty()) {
+        Some(end) => (&rest[..end], &rest[end + 1..]),
+        None => (rest, &[][..]),
+    };
+    let to_owned = |fields: &[&str]| fields.iter().map(|s| s.to_string()).collect();
+
+    Ok(Some(Sha1Line {
+        line_number,
+        file: FileRepr::new(name.to_owned(), size, sha1, block.into()).with_extra(to_owned(extra)),
+        folders: to_owned(folders),
+        encrypted,
+    }))
+}
+
+fn to_item(line: &str, line_number: usize) -> Option<LineItem> {
+    if let Some(path) = parse_folder_line(line) {
+        return Some(LineItem::Folder(path));
+    }
+    match parse_line(line, line_number) {
+        Ok(parsed) => parsed.map(|record| LineItem::File(Ok(record))),
+        Err(reason) => Some(LineItem::File(Err(RejectedLine {
+            line_number,
+            line: line.to_owned(),
+            reason,
+        }))),
+    }
+}
+
+/// every non blank line of an in-memory list, empty folders are left out
+pub(crate) fn parse_lines(content: &str) -> impl Iterator<Item = ParsedLine> + '_ {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| match to_item(line, i + 1)? {
+            LineItem::File(parsed) => Some(parsed),
+            LineItem::Folder(_) => None,
+        })
+}
+
+/// streams a line file from disk without loading it at once, files not in utf8 are decoded first
+pub(crate) struct Sha1LineReader {
+    lines: Lines<Box<dyn AsyncBufRead + Unpin + Send>>,
+    line_number: usize,
+    /// the text of the line last returned
+    line: String,
+}
+
+impl Sha1LineReader {
+    pub(crate) async fn open(path: &Path) -> Result<Self> {
+        check_input(path).await?;
+        Ok(Self {
+            lines: open_utf8(path).await?.lines(),
+            line_number: 0,
+            line: String::new(),
+        })
+    }
+
+    /// the next non blank line, `Ok(None)` at the end of the file
+    pub(crate) async fn next_item(&mut self) -> Result<Option<LineItem>> {
+        while let Some(line) = self
+            .lines
+            .next_line()
+            .await
+            .context("fail to read line, maybe not utf8?")?
+        {
+            self.line_number += 1;
+            let item = to_item(&line, self.line_number);
+            self.line = line;
+            if item.is_some() {
+                return Ok(item);
+            }
+        }
+        Ok(None)
+    }
+
+    /// the line last returned as it is in the file
+    pub(crate) fn line(&self) -> &str {
+        &self.line
+    }
+
+    /// the number of the line last returned, from 1
+    pub(crate) fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// the next line with a file, empty folders are skipped
+    pub(crate) async fn next_line(&mut self) -> Result<Option<ParsedLine>> {
+        while let Some(item) = self.next_item().await? {
+            if let LineItem::File(parsed) = item {
+                return Ok(Some(parsed));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789ABCDEF0123456789ABCDEF01234567";
+    const PREID: &str = "gByoftr5SYpf+jtQRwgHf4AcqH7a+UmKX/o7UEcIB3/Gk6uBlHEC89Z09QZByUp3";
+
+    fn error(line: &str) -> LineError {
+        parse_line(line, 1).unwrap_err()
+    }
+
+    #[test]
+    fn rejects_each_kind_of_bad_line() {
+        assert_eq!(error("115://a|1|2"), LineError::TooFewFields(3));
+        assert_eq!(
+            error(&format!("115://|1|{}|{}", HASH, HASH)),
+            LineError::EmptyName
+        );
+        assert_eq!(
+            error(&format!("115://a|1k|{}|{}", HASH, HASH)),
+            LineError::BadSize("1k".to_owned())
+        );
+        assert_eq!(
+            error(&format!("115://a|1|{}|{}", &HASH[1..], HASH)),
+            LineError::BadSha1Length(39)
+        );
+        let not_hex = HASH.replace('A', "G");
+        assert_eq!(
+            error(&format!("115://a|1|{}|{}", not_hex, HASH)),
+            LineError::BadSha1Hex
+        );
+        assert_eq!(
+            error(&format!("115://a|1|{}|error", HASH)),
+            LineError::MissingBlockHash
+        );
+        assert_eq!(
+            error(&format!("115://a|1|{}|{}", HASH, &HASH[2..])),
+            LineError::BadBlockHashLength(38)
+        );
+        assert_eq!(
+            error(&format!("115://a|1|{}|{}", HASH, not_hex)),
+            LineError::BadBlockHash
+        );
+        assert_eq!(
+            error(&format!("115://a|1|{}|{}", HASH, &PREID[4..])),
+            LineError::BadBlockHash
+        );
+    }
+
+    #[test]
+    fn accepts_plain_and_encrypted_blocks() {
+        assert!(parse_line("  ", 1).unwrap().is_none());
+        let plain = parse_line(&format!("115://a|1|{}|{}", HASH, HASH), 1)
+            .unwrap()
+            .unwrap();
+        assert!(!plain.encrypted);
+        let short = parse_line(&format!("115://a|1|{}|{}", HASH, PREID), 1)
+            .unwrap()
+            .unwrap();
+        assert!(short.encrypted);
+        let full = format!("115://a|1|{}|{}42IcwVjnnGHZB9ehzW+Pew==", HASH, PREID);
+        assert!(parse_line(&full, 1).unwrap().unwrap().encrypted);
+    }
+
+    #[test]
+    fn splits_folders_from_extra_fields() {
+        let line = format!("115://a.mkv|1|{}|{}|dir|sub||note|x", HASH, HASH);
+        let record = parse_line(&line, 3).unwrap().unwrap();
+        assert_eq!(record.line_number, 3);
+        assert_eq!(record.folders, ["dir", "sub"]);
+        assert_eq!(record.file.extra(), ["note", "x"]);
+        assert_eq!(record.trailing_fields(), ["dir", "sub", "", "note", "x"]);
+        assert_eq!(record.to_line(), line);
+    }
+
+    #[test]
+    fn reads_empty_folder_lines() {
+        assert_eq!(
+            parse_folder_line("||||a|b"),
+            Some(vec!["a".to_owned(), "b".to_owned()])
+        );
+        assert_eq!(parse_folder_line("||||"), None);
+        let content = format!("||||a\n\n115://a|1|{}|{}\n", HASH, HASH);
+        assert_eq!(parse_lines(&content).count(), 1);
+    }
+}
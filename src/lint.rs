@@ -0,0 +1,393 @@
+///
+/// entries that parse fine but are useless or fail on import
+///
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use crate::line_parser::Sha1Line;
+use crate::parsers::{BlockHash, FileRepr, Sha1Entity, Sha1Hash};
+
+/// sha1 of zero bytes, DA39A3EE5E6B4B0D3255BFEF95601890AFD80709
+const EMPTY_SHA1: Sha1Hash = Sha1Hash::from_bytes([
+    0xda, 0x39, 0xa3, 0xee, 0x5e, 0x6b, 0x4b, 0x0d, 0x32, 0x55, 0xbf, 0xef, 0x95, 0x60, 0x18, 0x90,
+    0xaf, 0xd8, 0x07, 0x09,
+]);
+/// the block sha1 covers this many leading bytes
+const BLOCK_SIZE: u64 = 128 * 1024;
+/// longest file or folder name that still imports
+const MAX_NAME_CHARS: usize = 255;
+/// longest full path that still imports
+const MAX_PATH_CHARS: usize = 1024;
+/// examples listed per rule in the chat
+const EXAMPLES_PER_RULE: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum LintRule {
+    ZeroByte,
+    /// the sha1 of nothing on a file that has a size
+    EmptySha1,
+    /// the block sha1 can only equal the sha1 when the file fits in one block
+    BlockSha1IsSha1,
+    ForbiddenChars,
+    NameTooLong,
+    PathTooLong,
+    TrailingDotOrSpace,
+}
+
+impl LintRule {
+    /// dropped or renamed by `fix_entity` and `fix_lines`
+    pub(crate) fn fixable(&self) -> bool {
+        !matches!(self, LintRule::BlockSha1IsSha1 | LintRule::PathTooLong)
+    }
+}
+
+impl fmt::Display for LintRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LintRule::ZeroByte => "空文件",
+            LintRule::EmptySha1 => "sha1 是空文件的值但大小不为 0",
+            LintRule::BlockSha1IsSha1 => "块 sha1 与完整 sha1 相同但文件大于 128K",
+            LintRule::ForbiddenChars => "名称包含 115 不允许的字符",
+            LintRule::NameTooLong => "名称过长",
+            LintRule::PathTooLong => "路径过长",
+            LintRule::TrailingDotOrSpace => "名称以点或空格结尾",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn is_forbidden(c: char) -> bool {
+    matches!(c, '\\' | '/' | ':' | '*' | '?' | '"' | '<' | '>' | '|') || c.is_control()
+}
+
+/// rules a file or folder name breaks
+fn check_name(name: &str) -> Vec<LintRule> {
+    let mut rules = Vec::new();
+    if name.chars().any(is_forbidden) {
+        rules.push(LintRule::ForbiddenChars);
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        rules.push(LintRule::NameTooLong);
+    }
+    if name.ends_with(['.', ' ']) {
+        rules.push(LintRule::TrailingDotOrSpace);
+    }
+    rules
+}
+
+/// rules a file breaks by itself, its name included
+pub(crate) fn check_file(file: &FileRepr) -> Vec<LintRule> {
+    let mut rules = Vec::new();
+    if file.size() == 0 {
+        rules.push(LintRule::ZeroByte);
+    } else if *file.sha1() == EMPTY_SHA1 {
+        rules.push(LintRule::EmptySha1);
+    }
+    if file.size() > BLOCK_SIZE && *file.sha1_block() == BlockHash::Plain(*file.sha1()) {
+        rules.push(LintRule::BlockSha1IsSha1);
+    }
+    rules.extend(check_name(file.name()));
+    rules
+}
+
+/// a name 115 takes, `None` when nothing is left of it
+fn fix_name(name: &str) -> Option<String> {
+    let replaced: String = name
+        .chars()
+        .map(|c| if is_forbidden(c) { '_' } else { c })
+        .collect();
+    let mut fixed = replaced.trim_end_matches(['.', ' ']).to_owned();
+    if fixed.chars().count() > MAX_NAME_CHARS {
+        // cut the stem, keep the extension
+        let (stem, ext) = match fixed.rsplit_once('.') {
+            Some((stem, ext)) if ext.chars().count() < MAX_NAME_CHARS / 2 => {
+                (stem.to_owned(), format!(".{}", ext))
+            }
+            _ => (fixed.to_owned(), String::new()),
+        };
+        let keep = MAX_NAME_CHARS - ext.chars().count();
+        fixed = stem.chars().take(keep).collect::<String>() + &ext;
+        fixed = fixed.trim_end_matches(['.', ' ']).to_owned();
+    }
+    if fixed.is_empty() {
+        None
+    } else {
+        Some(fixed)
+    }
+}
+
+/// the file with a name 115 takes, `None` when it is better dropped
+fn fix_file(mut file: FileRepr) -> Option<FileRepr> {
+    let rules = check_file(&file);
+    if rules.contains(&LintRule::ZeroByte) || rules.contains(&LintRule::EmptySha1) {
+        return None;
+    }
+    let name = fix_name(file.name())?;
+    file.set_name(name);
+    Some(file)
+}
+
+#[derive(Default)]
+pub(crate) struct LintReport {
+    /// where each rule is broken, a folder shows up once
+    issues: BTreeMap<LintRule, Vec<String>>,
+    seen: HashSet<(LintRule, String)>,
+}
+
+impl LintReport {
+    fn add(&mut self, rules: Vec<LintRule>, location: &str) {
+        for rule in rules {
+            if self.seen.insert((rule, location.to_owned())) {
+                self.issues
+                    .entry(rule)
+                    .or_default()
+                    .push(location.to_owned());
+            }
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// places that break a rule `fix_entity` and `fix_lines` leave alone
+    pub(crate) fn unfixable(&self) -> usize {
+        self.issues
+            .iter()
+            .filter(|(rule, _)| !rule.fixable())
+            .map(|(_, locations)| locations.len())
+            .sum()
+    }
+
+    pub(crate) fn has_fixable(&self) -> bool {
+        self.issues.keys().any(LintRule::fixable)
+    }
+
+    /// a folder path, checked for its last name and its length
+    fn check_folder(&mut self, path: &str, name: &str) {
+        let mut rules = check_name(name);
+        if path.chars().count() > MAX_PATH_CHARS {
+            rules.push(LintRule::PathTooLong);
+        }
+        self.add(rules, path);
+    }
+
+    fn check_file_at(&mut self, folder: &str, file: &FileRepr) {
+        let path = if folder.is_empty() {
+            file.name().to_owned()
+        } else {
+            format!("{}/{}", folder, file.name())
+        };
+        let mut rules = check_file(file);
+        if path.chars().count() > MAX_PATH_CHARS {
+            rules.push(LintRule::PathTooLong);
+        }
+        self.add(rules, &path);
+    }
+}
+
+fn shorten(s: &str) -> String {
+    if s.chars().count() > 40 {
+        let tail: String = s
+            .chars()
+            .rev()
+            .take(37)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect();
+        format!("...{}", tail)
+    } else {
+        s.to_owned()
+    }
+}
+
+impl fmt::Display for LintReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for (rule, locations) in &self.issues {
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            let fixable = if rule.fixable() {
+                ""
+            } else {
+                ", 无法自动修复"
+            };
+            write!(f, "{}: {} 处{}", rule, locations.len(), fixable)?;
+            for location in locations.iter().take(EXAMPLES_PER_RULE) {
+                write!(f, "\n  {}", shorten(location))?;
+            }
+            if locations.len() > EXAMPLES_PER_RULE {
+                write!(f, "\n  ...")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn lint_dir(report: &mut LintReport, entity: &Sha1Entity, parent: &str) {
+    let path = if parent.is_empty() {
+        entity.dir_name.to_owned()
+    } else {
+        format!("{}/{}", parent, entity.dir_name)
+    };
+    report.check_folder(&path, &entity.dir_name);
+    for file in &entity.files {
+        report.check_file_at(&path, file);
+    }
+    for dir in &entity.dirs {
+        lint_dir(report, dir, &path);
+    }
+}
+
+pub(crate) fn lint_entity(entity: &Sha1Entity) -> LintReport {
+    let mut report = LintReport::default();
+    lint_dir(&mut report, entity, "");
+    report
+}
+
+pub(crate) fn lint_lines(records: &[Sha1Line]) -> LintReport {
+    let mut report = LintReport::default();
+    for record in records {
+        let mut path = String::new();
+        for folder in &record.folders {
+            if !path.is_empty() {
+                path.push('/');
+            }
+            path.push_str(folder);
+            report.check_folder(&path, folder);
+        }
+        report.check_file_at(&path, &record.file);
+    }
+    report
+}
+
+/// sibling folders renamed to the same name become one, as lines with the same path would
+fn merge_siblings(dirs: Vec<Sha1Entity>) -> Vec<Sha1Entity> {
+    let mut index: HashMap<String, usize> = HashMap::new();
+    let mut merged: Vec<Sha1Entity> = Vec::new();
+    for dir in dirs {
+        match index.get(&dir.dir_name) {
+            Some(i) => {
+                let same = &mut merged[*i];
+                same.files.extend(dir.files);
+                same.dirs.extend(dir.dirs);
+                same.dirs = merge_siblings(std::mem::take(&mut same.dirs));
+            }
+            None => {
+                index.insert(dir.dir_name.to_owned(), merged.len());
+                merged.push(dir);
+            }
+        }
+    }
+    merged
+}
+
+/// drops and renames what can be fixed, returns how many files were dropped
+pub(crate) fn fix_entity(entity: &mut Sha1Entity) -> usize {
+    if let Some(name) = fix_name(&entity.dir_name) {
+        entity.dir_name = name;
+    }
+    let before = entity.files.len();
+    entity.files = std::mem::take(&mut entity.files)
+        .into_iter()
+        .filter_map(fix_file)
+        .collect();
+    let mut dropped = before - entity.files.len();
+    for dir in entity.dirs.iter_mut() {
+        dropped += fix_entity(dir);
+    }
+    entity.dirs = merge_siblings(std::mem::take(&mut entity.dirs));
+    dropped
+}
+
+/// drops and renames what can be fixed, returns the lines left and how many were dropped
+pub(crate) fn fix_lines(records: Vec<Sha1Line>) -> (Vec<Sha1Line>, usize) {
+    let before = records.len();
+    let fixed: Vec<Sha1Line> = records
+        .into_iter()
+        .filter_map(|mut record| {
+            record.file = fix_file(record.file)?;
+            for folder in record.folders.iter_mut() {
+                if let Some(name) = fix_name(folder) {
+                    *folder = name;
+                }
+            }
+            Some(record)
+        })
+        .collect();
+    let dropped = before - fixed.len();
+    (fixed, dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_parser::parse_lines;
+    use crate::parsers::entity_from_lines;
+
+    #[test]
+    fn long_names_keep_their_extension() {
+        let name = format!("{}.mkv", "中".repeat(300));
+        let fixed = fix_name(&name).unwrap();
+        assert_eq!(fixed.chars().count(), MAX_NAME_CHARS);
+        assert_eq!(fixed, format!("{}.mkv", "中".repeat(MAX_NAME_CHARS - 4)));
+
+        // an extension that long is not one
+        let name = format!("{}.{}", "a".repeat(100), "b".repeat(200));
+        assert_eq!(
+            fix_name(&name).unwrap(),
+            name.chars().take(MAX_NAME_CHARS).collect::<String>()
+        );
+
+        // no dot or space left at the cut
+        let name = format!("{} {}", "a".repeat(254), "b".repeat(10));
+        assert_eq!(fix_name(&name).unwrap(), "a".repeat(254));
+        assert_eq!(
+            fix_name(&"a".repeat(MAX_NAME_CHARS)).unwrap(),
+            "a".repeat(MAX_NAME_CHARS)
+        );
+    }
+
+    #[test]
+    fn names_lose_forbidden_chars_and_trailing_dots() {
+        assert_eq!(fix_name("a:b?.txt").unwrap(), "a_b_.txt");
+        assert_eq!(fix_name("folder. . ").unwrap(), "folder");
+        assert_eq!(fix_name("..."), None);
+        assert_eq!(fix_name("fine.txt").unwrap(), "fine.txt");
+    }
+
+    #[test]
+    fn renamed_sibling_folders_merge() {
+        let hash = "1".repeat(40);
+        let content = [
+            "x|1|root|a:b",
+            "w|1|root|a:b|s",
+            "y|1|root|a_b",
+            "z|1|root|a_b|s.",
+            "empty|0|root|a?b",
+            "c|1|root|c",
+        ]
+        .iter()
+        .map(|line| {
+            let (name, rest) = line.split_once('|').unwrap();
+            let (size, path) = rest.split_once('|').unwrap();
+            format!("115://{}|{}|{}|{}|{}\n", name, size, hash, hash, path)
+        })
+        .collect::<String>();
+        let mut entity = entity_from_lines(parse_lines(&content).flatten()).unwrap();
+
+        assert_eq!(fix_entity(&mut entity), 1);
+        let names: Vec<_> = entity.dirs.iter().map(|d| d.dir_name.as_str()).collect();
+        assert_eq!(names, ["a_b", "c"]);
+        let merged = &entity.dirs[0];
+        let files: Vec<_> = merged.files.iter().map(|f| f.name()).collect();
+        assert_eq!(files, ["x", "y"]);
+        assert_eq!(merged.dirs.len(), 1);
+        let files: Vec<_> = merged.dirs[0].files.iter().map(|f| f.name()).collect();
+        assert_eq!(files, ["w", "z"]);
+        assert!(lint_entity(&entity).is_empty());
+    }
+}
@@ -0,0 +1,327 @@
+///
+/// relating sha1 lists to each other, files are the same when `FileRepr::unique_key` is
+///
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::line_parser::{parse_lines, Sha1Line, Sha1LineReader};
+use crate::parsers::{
+    entity_from_lines, entity_lines, path_to_sha1_entity, to_iec, write_all_to_file, FileKey,
+    Sha1Entity,
+};
+use crate::sha1_json::{export_sha1_json, JsonFormat};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SetOp {
+    Union,
+    Intersection,
+    /// the first list minus every other
+    Difference,
+    /// files that are in exactly one of the lists
+    SymmetricDifference,
+}
+
+impl FromStr for SetOp {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "union" | "并集" => SetOp::Union,
+            "inter" | "intersection" | "交集" => SetOp::Intersection,
+            "diff" | "difference" | "差集" => SetOp::Difference,
+            "xor" | "symdiff" | "对称差" => SetOp::SymmetricDifference,
+            _ => bail!("unknown set operation: {}", s),
+        })
+    }
+}
+
+impl fmt::Display for SetOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SetOp::Union => "并集",
+            SetOp::Intersection => "交集",
+            SetOp::Difference => "差集",
+            SetOp::SymmetricDifference => "对称差",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// a list file to operate on and the name it is reported under
+pub(crate) struct ListInput {
+    pub(crate) name: String,
+    pub(crate) path: PathBuf,
+}
+
+struct Count {
+    files: usize,
+    size: u64,
+}
+
+impl fmt::Display for Count {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} 个文件, {}", self.files, to_iec(self.size))
+    }
+}
+
+pub(crate) struct SetReport {
+    op: SetOp,
+    inputs: Vec<(String, Count)>,
+    result: Count,
+}
+
+impl SetReport {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.result.files == 0
+    }
+}
+
+impl fmt::Display for SetReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}, 共 {} 个列表:", self.op, self.inputs.len())?;
+        for (name, count) in &self.inputs {
+            writeln!(f, "{}: {}", name, count)?;
+        }
+        write!(f, "结果: {}", self.result)
+    }
+}
+
+/// every valid line of a txt or json list, and the shape of the json
+pub(crate) async fn load_list(path: &Path) -> Result<(Vec<Sha1Line>, Option<JsonFormat>)> {
+    let is_json = path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("json"))
+        .unwrap_or(false);
+    if is_json {
+        let (entity, format) = path_to_sha1_entity(path).await?;
+        let lines = entity_lines(&entity, false);
+        return Ok((parse_lines(&lines).flatten().collect(), Some(format)));
+    }
+
+    let mut reader = Sha1LineReader::open(path).await?;
+    let mut list = Vec::new();
+    while let Some(parsed) = reader.next_line().await? {
+        if let Ok(record) = parsed {
+            list.push(record);
+        }
+    }
+    Ok((list, None))
+}
+
+/// lines back into the content of a list file, json in the shape it came in
+pub(crate) fn list_bytes(records: Vec<Sha1Line>, format: Option<JsonFormat>) -> Result<Vec<u8>> {
+    match format {
+        Some(format) => export_sha1_json(&entity_from_lines(records)?, format),
+        None => {
+            let mut content = String::new();
+            for record in records {
+                content.push_str(&record.to_line());
+                content.push('\n');
+            }
+            Ok(content.into_bytes())
+        }
+    }
+}
+
+/// the folder tree of a txt or json list, and the shape of the json
+pub(crate) async fn load_entity(path: &Path) -> Result<(Sha1Entity, Option<JsonFormat>)> {
+    let (records, format) = load_list(path).await?;
+    Ok((entity_from_lines(records)?, format))
+}
+
+/// a tree back into the content of a list file, json in the shape it came in
+pub(crate) fn entity_bytes(entity: &Sha1Entity, format: Option<JsonFormat>) -> Result<Vec<u8>> {
+    match format {
+        Some(format) => export_sha1_json(entity, format),
+        None => Ok(entity_lines(entity, false).into_bytes()),
+    }
+}
+
+/// file extension `list_bytes` output should be saved with
+pub(crate) fn list_extension(format: Option<JsonFormat>) -> &'static str {
+    match format {
+        Some(_) => "json",
+        None => "txt",
+    }
+}
+
+fn unique_count(list: &[Sha1Line]) -> Count {
+    let mut seen = HashSet::new();
+    let mut count = Count { files: 0, size: 0 };
+    for record in list {
+        if seen.insert(record.file.unique_key()) {
+            count.files += 1;
+            count.size += record.file.size();
+        }
+    }
+    count
+}
+
+/// the result keeps the first line seen for each file, folder info included
+fn apply(op: SetOp, lists: &[Vec<Sha1Line>]) -> Vec<&Sha1Line> {
+    // in how many lists each file shows up
+    let mut hits: HashMap<FileKey, usize> = HashMap::new();
+    for list in lists {
+        let keys: HashSet<FileKey> = list.iter().map(|r| r.file.unique_key()).collect();
+        for key in keys {
+            *hits.entry(key).or_default() += 1;
+        }
+    }
+
+    let candidates = match op {
+        SetOp::Union | SetOp::SymmetricDifference => lists,
+        SetOp::Intersection | SetOp::Difference => &lists[..1],
+    };
+    let wanted = |n: usize| match op {
+        SetOp::Union => true,
+        SetOp::Intersection => n == lists.len(),
+        SetOp::Difference | SetOp::SymmetricDifference => n == 1,
+    };
+
+    let mut seen = HashSet::new();
+    candidates
+        .iter()
+        .flatten()
+        .filter(|record| {
+            let key = record.file.unique_key();
+            wanted(hits[&key]) && seen.insert(key)
+        })
+        .collect()
+}
+
+/// run `op` over the lists in order and write the resulting lines to `output`
+pub(crate) async fn set_operation(
+    op: SetOp,
+    inputs: &[ListInput],
+    output: &Path,
+) -> Result<SetReport> {
+    if inputs.len() < 2 {
+        bail!("set operation needs at least two lists");
+    }
+
+    let mut lists = Vec::new();
+    for input in inputs {
+        lists.push(load_list(&input.path).await?.0);
+    }
+
+    let result = apply(op, &lists);
+    let mut content = String::new();
+    let mut size = 0;
+    for record in &result {
+        content.push_str(&record.to_line());
+        content.push('\n');
+        size += record.file.size();
+    }
+    if !result.is_empty() {
+        write_all_to_file(output, content.as_bytes()).await?;
+    }
+
+    Ok(SetReport {
+        op,
+        inputs: inputs
+            .iter()
+            .zip(&lists)
+            .map(|(input, list)| (input.name.to_owned(), unique_count(list)))
+            .collect(),
+        result: Count {
+            files: result.len(),
+            size,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha1_json::parse_sha1_json;
+
+    fn list(names: &[&str]) -> Vec<Sha1Line> {
+        let content: String = names
+            .iter()
+            .map(|name| {
+                // the same content shares a hash whatever the file is called
+                let hash = name.trim_start_matches('_').repeat(40);
+                format!("115://{}|1|{}|{}|dir\n", name, hash, hash)
+            })
+            .collect();
+        parse_lines(&content).flatten().collect()
+    }
+
+    fn names(op: SetOp, lists: &[Vec<Sha1Line>]) -> Vec<String> {
+        apply(op, lists)
+            .iter()
+            .map(|r| r.file.name().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn set_operations() {
+        let lists = [
+            list(&["a", "b", "c"]),
+            list(&["_b", "c", "d"]),
+            list(&["c", "e"]),
+        ];
+        assert_eq!(names(SetOp::Union, &lists), ["a", "b", "c", "d", "e"]);
+        assert_eq!(names(SetOp::Intersection, &lists), ["c"]);
+        assert_eq!(names(SetOp::Difference, &lists), ["a"]);
+        assert_eq!(names(SetOp::SymmetricDifference, &lists), ["a", "d", "e"]);
+    }
+
+    #[test]
+    fn parses_set_operations() {
+        assert_eq!("交集".parse::<SetOp>().unwrap(), SetOp::Intersection);
+        assert_eq!("xor".parse::<SetOp>().unwrap(), SetOp::SymmetricDifference);
+        assert!("nope".parse::<SetOp>().is_err());
+    }
+
+    fn lines_of(json: &str) -> Vec<String> {
+        let entity = parse_sha1_json(json.as_bytes()).unwrap().0;
+        parse_lines(&entity_lines(&entity, false))
+            .flatten()
+            .map(|r| r.folders.join("/"))
+            .collect()
+    }
+
+    #[test]
+    fn json_lines_skip_the_synthetic_root() {
+        let link = format!("a|1|{}|{}", "1".repeat(40), "1".repeat(40));
+        let roots = format!(
+            r#"[{{"dir_name": "x", "files": ["{0}"], "dirs": []}},
+                {{"dir_name": "y", "files": ["{0}"], "dirs": []}}]"#,
+            link
+        );
+        assert_eq!(lines_of(&roots), ["x", "y"]);
+
+        let nameless = format!(
+            r#"{{"dir_name": "", "files": ["{0}"], "dirs": [
+                {{"dir_name": "x", "files": ["{0}"], "dirs": []}}]}}"#,
+            link
+        );
+        assert_eq!(lines_of(&nameless), ["", "x"]);
+    }
+
+    #[test]
+    fn json_export_keeps_files_without_folders() {
+        let hash = "2".repeat(40);
+        let content = format!("115://a|1|{0}|{0}\n115://b|2|{0}|{0}|dir\n", hash);
+        let formats = [
+            JsonFormat::Standard,
+            JsonFormat::Flat,
+            JsonFormat::EntityArray,
+            JsonFormat::ChildrenTree,
+        ];
+        for format in formats {
+            let only_loose = parse_lines(&content).flatten().take(1).collect();
+            let json = list_bytes(only_loose, Some(format)).unwrap();
+            let entity = parse_sha1_json(&json).unwrap().0;
+            assert_eq!(entity.files[0].name(), "a", "{:?}", format);
+
+            let mixed = parse_lines(&content).flatten().collect();
+            let json = String::from_utf8(list_bytes(mixed, Some(format)).unwrap()).unwrap();
+            assert_eq!(lines_of(&json), ["", "dir"], "{:?}", format);
+        }
+    }
+}
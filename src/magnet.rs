@@ -0,0 +1,193 @@
+///
+/// magnet uri parsing, every parameter instead of just the first btih
+///
+use anyhow::{anyhow, bail, Result};
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::Url;
+use std::str::FromStr;
+
+use crate::parsers::{base32_hex, to_iec};
+
+lazy_static! {
+    static ref MAGNET_URI_RE: Regex = Regex::new(r#"magnet:\?[^\s"'<>]+"#).unwrap();
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct MagnetLink {
+    /// v1 infohash, uppercase hex
+    pub(crate) btih: Option<String>,
+    /// v2 infohash (sha256 without the multihash prefix), lowercase hex
+    pub(crate) btmh: Option<String>,
+    /// `dn`
+    pub(crate) display_name: Option<String>,
+    /// `xl`
+    pub(crate) exact_length: Option<u64>,
+    /// `tr`
+    pub(crate) trackers: Vec<String>,
+    /// `ws`
+    pub(crate) web_seeds: Vec<String>,
+}
+
+impl FromStr for MagnetLink {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let url = Url::parse(s)?;
+        if url.scheme() != "magnet" {
+            bail!("not a magnet link");
+        }
+
+        let mut magnet = MagnetLink::default();
+        for (key, value) in url.query_pairs() {
+            // indexed parameters such as `xt.1` or `tr.2` count as their plain form
+            let key = key.split('.').next().unwrap_or("");
+            match key {
+                "xt" => {
+                    if let Some(hash) = value.strip_prefix("urn:btih:") {
+                        magnet.btih = Some(match hash.len() {
+                            40 if hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+                                hash.to_ascii_uppercase()
+                            }
+                            32 => base32_hex(&hash.to_ascii_uppercase())?,
+                            _ => bail!("invalid btih: {}", hash),
+                        });
+                    } else if let Some(hash) = value.strip_prefix("urn:btmh:") {
+                        let hash = hash
+                            .strip_prefix("1220")
+                            .filter(|h| h.len() == 64 && h.chars().all(|c| c.is_ascii_hexdigit()))
+                            .ok_or_else(|| anyhow!("unsupported btmh: {}", hash))?;
+                        magnet.btmh = Some(hash.to_ascii_lowercase());
+                    }
+                }
+                "dn" => magnet.display_name = Some(value.into_owned()),
+                "xl" => magnet.exact_length = value.parse().ok(),
+                "tr" => magnet.trackers.push(value.into_owned()),
+                "ws" => magnet.web_seeds.push(value.into_owned()),
+                _ => {}
+            }
+        }
+
+        if magnet.btih.is_none() && magnet.btmh.is_none() {
+            bail!("magnet link without bittorrent infohash");
+        }
+        Ok(magnet)
+    }
+}
+
+impl MagnetLink {
+    /// name and size are in the link itself, no need to look the torrent up
+    pub(crate) fn is_self_describing(&self) -> bool {
+        self.display_name.is_some() && self.exact_length.is_some()
+    }
+
+    /// a plain text summary from the link parameters only
+    pub(crate) fn summary(&self) -> String {
+        let mut res = match self.exact_length {
+            Some(length) => format!("总计: {}", to_iec(length)),
+            None => "总计: 未知".to_string(),
+        };
+        if !self.trackers.is_empty() {
+            res.push_str(&format!("\nTracker: {} 个", self.trackers.len()));
+        }
+        if !self.web_seeds.is_empty() {
+            res.push_str(&format!("\nWeb Seed: {} 个", self.web_seeds.len()));
+        }
+        res
+    }
+}
+
+/// every magnet uri found in the text, unparsable ones are left out
+pub(crate) fn all_magnet_links(text: &str) -> Vec<MagnetLink> {
+    MAGNET_URI_RE
+        .find_iter(text)
+        .filter_map(|m| MagnetLink::from_str(m.as_str()).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEX: &str = "7FAF75B2447F88700C68F1ECEDA713CD90A0127A";
+    const BASE32: &str = "P6XXLMSEP6EHADDI6HWO3JYTZWIKAET2";
+    const SHA256: &str = "caf1e1c30e81cb361b9ee167c4aa64228a7fa4fa9f6105232b28ad099f3a302e";
+
+    fn parse(s: &str) -> MagnetLink {
+        MagnetLink::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn btih_in_hex_and_base32() {
+        let lower = parse(&format!("magnet:?xt=urn:btih:{}", HEX.to_ascii_lowercase()));
+        assert_eq!(lower.btih.as_deref(), Some(HEX));
+        let base32 = parse(&format!(
+            "magnet:?xt=urn:btih:{}",
+            BASE32.to_ascii_lowercase()
+        ));
+        assert_eq!(base32.btih.as_deref(), Some(HEX));
+        assert!(MagnetLink::from_str("magnet:?xt=urn:btih:1234").is_err());
+    }
+
+    #[test]
+    fn btmh_next_to_btih() {
+        let link = format!(
+            "magnet:?xt=urn:btih:{}&xt=urn:btmh:1220{}",
+            HEX,
+            SHA256.to_ascii_uppercase()
+        );
+        let magnet = parse(&link);
+        assert_eq!(magnet.btih.as_deref(), Some(HEX));
+        assert_eq!(magnet.btmh.as_deref(), Some(SHA256));
+
+        let only_v2 = parse(&format!("magnet:?xt.1=urn:btmh:1220{}", SHA256));
+        assert_eq!(only_v2.btih, None);
+        assert_eq!(only_v2.btmh.as_deref(), Some(SHA256));
+        // another hash function than sha256
+        assert!(MagnetLink::from_str(&format!("magnet:?xt=urn:btmh:1320{}", SHA256)).is_err());
+    }
+
+    #[test]
+    fn parameters_are_percent_decoded() {
+        let link = format!(
+            "magnet:?xt=urn:btih:{}&dn=%E4%B8%AD%E6%96%87+name%26more&xl=1024\
+             &tr=udp%3A%2F%2Ftracker.example%3A80&tr.1=http://b.example/announce\
+             &ws=https%3A%2F%2Fseed.example%2Fa%20b",
+            HEX
+        );
+        let magnet = parse(&link);
+        assert_eq!(magnet.display_name.as_deref(), Some("中文 name&more"));
+        assert_eq!(magnet.exact_length, Some(1024));
+        assert_eq!(
+            magnet.trackers,
+            ["udp://tracker.example:80", "http://b.example/announce"]
+        );
+        assert_eq!(magnet.web_seeds, ["https://seed.example/a b"]);
+        assert!(magnet.is_self_describing());
+        let summary = format!("总计: {}\nTracker: 2 个\nWeb Seed: 1 个", to_iec(1024u64));
+        assert_eq!(magnet.summary(), summary);
+    }
+
+    #[test]
+    fn missing_dn_and_xl() {
+        let magnet = parse(&format!("magnet:?xt=urn:btih:{}&xl=abc", HEX));
+        assert_eq!(magnet.display_name, None);
+        assert_eq!(magnet.exact_length, None);
+        assert!(!magnet.is_self_describing());
+        assert_eq!(magnet.summary(), "总计: 未知");
+        assert!(MagnetLink::from_str("magnet:?dn=a").is_err());
+        assert!(MagnetLink::from_str("https://example.org/?xt=urn:btih:0").is_err());
+    }
+
+    #[test]
+    fn finds_links_in_text() {
+        let text = format!(
+            "a magnet:?xt=urn:btih:{0}&dn=a\n<magnet:?xt=urn:btih:bad> \"magnet:?xt=urn:btih:{1}\"",
+            HEX, BASE32
+        );
+        let found = all_magnet_links(&text);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].display_name.as_deref(), Some("a"));
+        assert_eq!(found[1].btih.as_deref(), Some(HEX));
+    }
+}
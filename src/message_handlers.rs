@@ -1,18 +1,33 @@
 use crate::{
-    decryption::{format_path_str, preid_decrypt},
-    global::{Bot, DEBUG_CC_ID, ROOT_FOLDER},
+    decryption::{format_path_str, DecryptReport, PreidDecoder},
+    global::{Bot, DEBUG_CC_ID, LOCAL_BOT_API, MAX_FILE_SIZE, ROOT_FOLDER},
     parsers::{
         all_ed2k_from_file, all_magnet_from_file, all_magnet_from_text, check_dup_n_err,
         decrypt_line_file, file_encoding, file_to_utf8, is_valid_line, json_summary, line_summary,
-        path_to_sha1_entity, write_all_to_file, Sha1Entity, Summary,
+        line_summary_mem, path_to_sha1_entity, write_all_to_file,
     },
 };
 
+use crate::archive::{read_archive, ArchiveKind};
 use crate::commands::Command;
+use crate::conflicts::{find_conflicts, resolve_conflicts, KeepPolicy};
+use crate::du::{du_report, du_table, DU_REPORT_DEPTH, DU_TABLE_ROWS};
+use crate::filter::Filter;
 use crate::global::{HELP, VERSION};
-use crate::parsers::{
-    base32_hex, get_torrent_magnet_async, get_torrent_summary_async, magnet_info, to_iec,
+use crate::line_parser::parse_line;
+use crate::lint::{lint_entity, lint_lines};
+use crate::list_ops::{
+    entity_bytes, list_bytes, list_extension, load_entity, load_list, set_operation, ListInput,
+    SetOp,
 };
+use crate::magnet::all_magnet_links;
+use crate::parsers::{magnet_info, to_iec};
+use crate::repair::repair_file;
+use crate::sha1_csv::{csv2line, is_sha1_csv};
+use crate::sha1_json::JsonFormat;
+use crate::split::{split_list, zip_parts, SplitMode, ZIP_THRESHOLD};
+use crate::torrent::TorrentMeta;
+use crate::tree_ops::parse_tree_ops;
 use anyhow::{anyhow, bail, Result};
 use chrono::Utc;
 use data_encoding::BASE32_NOPAD;
@@ -21,9 +36,11 @@ use regex::Regex;
 use scopeguard::defer;
 use sqlx::{Row, SqlitePool};
 use std::{
+    collections::HashMap,
     fs::remove_file,
     path::{Path, PathBuf},
-    time::Duration,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
 };
 use teloxide::utils::command::BotCommand;
 use teloxide::{
@@ -36,6 +53,7 @@ use teloxide::{
     },
 };
 use teloxide::types::ParseMode;
+use teloxide::utils::html::escape;
 use tokio::{fs::File, time::sleep};
 
 fn btn(
@@ -82,7 +100,13 @@ pub(crate) async fn copied(bot: &Bot, msg: &Message) -> Result<Message> {
     Ok(req.await?)
 }
 
+/// files above `MAX_FILE_SIZE` are refused, whichever handler asks for them
 pub(crate) async fn download_file(bot: &Bot, doc: &Document) -> Result<PathBuf> {
+    if let Some(size) = doc.file_size {
+        if size as u64 > unsafe { MAX_FILE_SIZE } {
+            bail!("file is too large to download: {}", to_iec(size as u64));
+        }
+    }
     let mut count = 0;
     loop {
         count += 1;
@@ -112,8 +136,13 @@ async fn download_file_proxy(bot: &Bot, doc: &Document) -> Result<PathBuf> {
     if path.exists() {
         let _ = std::fs::remove_file(path);
     }
-    let mut new_file = File::create(path).await?;
-    bot.download_file(&file_path, &mut new_file).await?;
+    if unsafe { LOCAL_BOT_API } {
+        // the local server already has the file on disk
+        tokio::fs::copy(&file_path, path).await?;
+    } else {
+        let mut new_file = File::create(path).await?;
+        bot.download_file(&file_path, &mut new_file).await?;
+    }
 
     Ok(path.to_path_buf())
 }
@@ -127,7 +156,12 @@ pub(crate) async fn line_handler(cx: &UpdateWithCx<Bot, Message>, doc: &Document
     let path = download_file(bot, doc).await?;
     let mut cached = false;
 
-    is_valid_line(&path).await?;
+    if let Err(e) = is_valid_line(&path).await {
+        // a mangled list may still be readable once repaired
+        let repaired = msg.chat.is_private() && reply_repaired(cx, doc, &path).await?;
+        let _ = std::fs::remove_file(&path);
+        return if repaired { Ok(()) } else { Err(e) };
+    }
 
     let summary = line_summary(&path).await.map_err(|e| {
         let _ = std::fs::remove_file(&path);
@@ -136,7 +170,7 @@ pub(crate) async fn line_handler(cx: &UpdateWithCx<Bot, Message>, doc: &Document
 
     let _ = copied(bot, msg).await;
 
-    let mut send_str = summary.to_string();
+    let mut send_str = summary.for_chat(msg.chat.is_private());
     let mut request = cx.reply_to(&send_str);
 
     if summary.encrypted {
@@ -161,6 +195,12 @@ pub(crate) async fn line_handler(cx: &UpdateWithCx<Bot, Message>, doc: &Document
 
         let output_path = format!("{}/{}", ROOT_FOLDER, new_filename);
         let output_path = Path::new(&output_path);
+        let failed_path = format!(
+            "{}/{}_解密失败.txt",
+            ROOT_FOLDER,
+            new_filename.strip_suffix(".txt").unwrap()
+        );
+        let failed_path = Path::new(&failed_path);
         defer! {
             if path.exists(){
                 let _ = remove_file(&path);
@@ -168,17 +208,28 @@ pub(crate) async fn line_handler(cx: &UpdateWithCx<Bot, Message>, doc: &Document
             if output_path.exists(){
                 let _ = remove_file(output_path);
             }
+            if failed_path.exists(){
+                let _ = remove_file(failed_path);
+            }
         }
 
-        decrypt_line_file(&path, output_path).await?;
-        send_str = format!("解密完成, {}", send_str);
-        reply_document_to(cx, output_path, msg, Some(send_str)).await?;
+        let report = decrypt_line_file(&path, output_path, failed_path).await?;
+        send_str = format!("{}\n{}", report, send_str);
+        if report.decrypted != 0 {
+            reply_document_to(cx, output_path, msg, Some(send_str)).await?;
+        } else {
+            cx.reply_to(send_str).await?;
+        }
+        if !report.failed.is_empty() {
+            reply_document_to(cx, failed_path, msg, None).await?;
+        }
 
         return Ok(());
     }
 
     if msg.chat.is_private() {
-        let (dup_num, invalid_num) = check_dup_n_err(&path).await?;
+        let (dup_num, rejected) = check_dup_n_err(&path).await?;
+        let invalid_num = rejected.len();
         if dup_num == 0 {
             send_str = format!("{}\n恭喜，这个文件没有重复文件链接。", &send_str);
         } else {
@@ -192,17 +243,36 @@ pub(crate) async fn line_handler(cx: &UpdateWithCx<Bot, Message>, doc: &Document
             );
         }
 
+        let (records, _) = load_list(&path).await?;
+        let conflicts = find_conflicts(&records);
+        if !conflicts.is_empty() {
+            send_str = format!("{}\n! {}", send_str, conflicts);
+        }
+        let lint = lint_lines(&records);
+        if !lint.is_empty() {
+            send_str = format!("{}\n\n语义检查:\n{}", send_str, lint);
+        }
+
         request = cx.reply_to(&send_str);
 
         let mut btns = InlineKeyboardMarkup::default();
         let len = doc.file_id.len();
         let last_part: String = doc.file_id.chars().skip(len - 62).collect();
 
+        let mut convert_row = vec![
+            btn("转成CSV", "2c", &last_part),
+            btn("转成TSV", "2t", &last_part),
+        ];
+        cached = true;
         if summary.has_folder {
-            cached = true;
-            let btn1 = btn("转成JSON", "2j", &last_part);
-            let btn2 = btn("去掉目录信息", "ls", &last_part);
-            btns = btns.append_row(vec![btn1, btn2]);
+            convert_row.insert(0, btn("转成JSON", "2j", &last_part));
+            btns = btns.append_row(convert_row);
+            btns = btns.append_row(vec![
+                btn("去掉目录信息", "ls", &last_part),
+                btn("目录大小", "du", &last_part),
+            ]);
+        } else {
+            btns = btns.append_row(convert_row);
         }
 
         let btn3 = btn("去除重复/无效文件", "ld", &last_part);
@@ -210,6 +280,23 @@ pub(crate) async fn line_handler(cx: &UpdateWithCx<Bot, Message>, doc: &Document
             btns = btns.append_row(vec![btn3]);
             cached = true;
         }
+
+        if invalid_num != 0 {
+            btns = btns.append_row(vec![
+                btn("导出错误行", "le", &last_part),
+                btn("自动修复格式", "rp", &last_part),
+            ]);
+            cached = true;
+        }
+
+        if !conflicts.is_empty() {
+            btns = btns.append_row(vec![btn("导出冲突报告", "cf", &last_part)]);
+        }
+
+        if lint.has_fixable() {
+            btns = btns.append_row(vec![btn("修复可修复的问题", "lf", &last_part)]);
+        }
+        btns = btns.append_row(vec![btn("加密块 sha1", "ec", &last_part)]);
         request = request.reply_markup(btns);
     }
 
@@ -228,23 +315,213 @@ pub(crate) async fn json_handler(cx: &UpdateWithCx<Bot, Message>, doc: &Document
     } = &cx;
 
     let path = download_file(bot, doc).await?;
-    let sha1: Sha1Entity = path_to_sha1_entity(&path).await?;
+    let (sha1, format) = match path_to_sha1_entity(&path).await {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            let _ = std::fs::remove_file(&path);
+            return Err(e);
+        }
+    };
     let _ = copied(bot, msg).await;
     let summary = json_summary(&sha1).map_err(|e| {
         let _ = std::fs::remove_file(&path);
         e
     })?;
 
-    let mut request = cx.reply_to(summary.to_string());
+    let mut send_str = format!(
+        "{}\n格式: {}",
+        summary.for_chat(msg.chat.is_private()),
+        format
+    );
+    let lint = lint_entity(&sha1);
+    if msg.chat.is_private() && !lint.is_empty() {
+        send_str = format!("{}\n\n语义检查:\n{}", send_str, lint);
+    }
+    let mut request = cx.reply_to(send_str);
+    if msg.chat.is_private() {
+        let len = doc.file_id.len();
+        let last_part: String = doc.file_id.chars().skip(len - 62).collect();
+        let exports: Vec<_> = JsonFormat::ALL
+            .iter()
+            .filter(|f| **f != format)
+            .map(|f| btn(format!("导出为{}", f), f.code(), &last_part))
+            .collect();
+        let mut btns = InlineKeyboardMarkup::default()
+            .append_row(vec![
+                btn("转成TXT", "2l", &last_part),
+                btn("转成CSV", "2c", &last_part),
+                btn("转成TSV", "2t", &last_part),
+            ])
+            .append_row(vec![
+                btn("转成TXT(保留空目录)", "2f", &last_part),
+                btn("目录大小", "du", &last_part),
+            ])
+            .append_row(exports);
+        if lint.has_fixable() {
+            btns = btns.append_row(vec![btn("修复可修复的问题", "lf", &last_part)]);
+        }
+        request = request.reply_markup(btns);
+    } else {
+        let _ = std::fs::remove_file(&path);
+    }
+    request.await?;
+    Ok(())
+}
+
+/// skipped rows of a sheet listed in the caption
+const CSV_SKIPPED_SHOWN: usize = 5;
+
+/// an edited csv / tsv sheet back into 115 links
+pub(crate) async fn csv_handler(cx: &UpdateWithCx<Bot, Message>, doc: &Document) -> Result<()> {
+    let UpdateWithCx {
+        requester: bot,
+        update: msg,
+    } = &cx;
+
+    if !msg.chat.is_private() {
+        return Ok(());
+    }
+
+    let path = download_file(bot, doc).await?;
+    let old_filename = doc
+        .file_name
+        .to_owned()
+        .unwrap_or_else(|| "default_filename.csv".to_string());
+    let stem = match old_filename.rsplit_once('.') {
+        Some((stem, _)) => stem.to_string(),
+        None => old_filename,
+    };
+    let output_path = format!(
+        "{}/{}_{}.txt",
+        ROOT_FOLDER,
+        stem,
+        BASE32_NOPAD.encode(&Utc::now().timestamp().to_ne_bytes())
+    );
+    let output_path = Path::new(&output_path);
+    defer! {
+        if path.exists(){
+            let _ = remove_file(&path);
+        }
+        if output_path.exists(){
+            let _ = remove_file(output_path);
+        }
+    }
+
+    if !is_sha1_csv(&path).await? {
+        return Ok(());
+    }
+    let _ = copied(bot, msg).await;
+
+    let (count, skipped) = csv2line(&path, output_path).await?;
+    let summary = line_summary(output_path).await?;
+    let mut caption = format!("{}\n已转换 {} 行", summary.detailed(), count);
+    if !skipped.is_empty() {
+        caption = format!("{}, 跳过 {} 行无效数据:", caption, skipped.len());
+        for reason in skipped.iter().take(CSV_SKIPPED_SHOWN) {
+            caption = format!("{}\n  {}", caption, reason);
+        }
+        if skipped.len() > CSV_SKIPPED_SHOWN {
+            caption.push_str("\n  ...");
+        }
+    }
+    reply_document_to(cx, output_path, msg, Some(caption)).await?;
+    Ok(())
+}
+
+/// a zip / gz / tar.gz bundle of lists, every member summarised and all of them together
+pub(crate) async fn archive_handler(cx: &UpdateWithCx<Bot, Message>, doc: &Document) -> Result<()> {
+    let UpdateWithCx {
+        requester: bot,
+        update: msg,
+    } = &cx;
+
+    let file_name = doc
+        .file_name
+        .to_owned()
+        .unwrap_or_else(|| "default_name.zip".to_string());
+    let path = download_file(bot, doc).await?;
+    let report = match read_archive(&path, &file_name) {
+        Ok(report) if !report.is_empty() => report,
+        res => {
+            // an archive without lists is none of our business
+            let _ = std::fs::remove_file(&path);
+            return res.map(|_| ());
+        }
+    };
+    let _ = copied(bot, msg).await;
+
+    let send_str = report.for_chat(msg.chat.is_private())?;
+    let mut request = cx.reply_to(send_str);
     if msg.chat.is_private() {
         let len = doc.file_id.len();
         let last_part: String = doc.file_id.chars().skip(len - 62).collect();
         let btns =
-            InlineKeyboardMarkup::default().append_row(vec![btn("转成TXT", "2l", last_part)]);
+            InlineKeyboardMarkup::default().append_row(vec![btn("合并去重", "am", &last_part)]);
+        request = request.reply_markup(btns);
+    } else {
+        let _ = std::fs::remove_file(&path);
+    }
+    request.await?;
+    Ok(())
+}
+
+pub(crate) async fn torrent_handler(cx: &UpdateWithCx<Bot, Message>, doc: &Document) -> Result<()> {
+    let UpdateWithCx {
+        requester: bot,
+        update: msg,
+    } = &cx;
+
+    let path = download_file(bot, doc).await?;
+    let meta = match TorrentMeta::from_path(&path).await {
+        Ok(meta) => meta,
+        Err(e) => {
+            let _ = std::fs::remove_file(&path);
+            return Err(e);
+        }
+    };
+
+    let mut reply = format!(
+        "<code>{}</code>\n---\n{} 种子, 共 {} 个文件, 总计: {}",
+        meta.magnet(),
+        meta.version,
+        meta.file_count(),
+        to_iec(meta.total_length())
+    );
+
+    if meta.file_count() > 1 {
+        reply.push_str("\n\n最大的文件:\n");
+        for file in meta.largest_files(5) {
+            reply.push_str(&format!(
+                "{:>7} => {}\n",
+                to_iec(file.length),
+                escape(file.path.last().map(String::as_str).unwrap_or(""))
+            ));
+        }
+        let mut tree = meta.folder_tree(2, 6);
+        if tree.chars().count() > 2000 {
+            tree = tree.chars().take(2000).collect::<String>() + "\n...";
+        }
+        reply.push_str(&format!("\n目录结构:\n<code>{}</code>", escape(&tree)));
+    }
+
+    let mut request = cx.reply_to(reply);
+    let payload = request.payload_mut();
+    payload.parse_mode = Some(ParseMode::Html);
+
+    if msg.chat.is_private() {
+        let len = doc.file_id.len();
+        let last_part: String = doc.file_id.chars().skip(len - 62).collect();
+        let btns = InlineKeyboardMarkup::default()
+            .append_row(vec![
+                btn("导出文件列表TXT", "tt", &last_part),
+                btn("导出文件列表JSON", "tj", &last_part),
+            ])
+            .append_row(vec![btn("种子详情", "ti", &last_part)]);
         request = request.reply_markup(btns);
     } else {
         let _ = std::fs::remove_file(&path);
     }
+
     request.await?;
     Ok(())
 }
@@ -267,16 +544,32 @@ pub(crate) async fn db_handler(cx: &UpdateWithCx<Bot, Message>, doc: &Document)
 "#).fetch_all(&pool).await?;
 
     let mut content: String = String::new();
+    let mut decoder = PreidDecoder::default();
+    let mut report = DecryptReport::default();
 
-    for row in rows {
+    for (i, row) in rows.into_iter().enumerate() {
         let head = row
             .try_get::<&str, usize>(0)?
             .replace(" ", "_")
             .replace("\\", "")
             .replace("\n", "");
-        let preid = preid_decrypt(row.try_get::<&str, usize>(1)?)?;
+        let fail = |reason: &str| format!("第 {} 条: {}\t{}", i + 1, reason, head);
+        let preid = match decoder.decrypt(row.try_get::<&str, usize>(1)?) {
+            Ok(preid) => preid,
+            Err(e) => {
+                log::warn!("failed to decrypt db row {}: {}", i + 1, e);
+                report.failed.push(fail("无法解密"));
+                continue;
+            }
+        };
         let path_str = row.try_get::<&str, usize>(2)?;
-        let path_str = format_path_str(path_str)?;
+        let path_str = match format_path_str(path_str) {
+            Ok(path_str) => path_str,
+            Err(_) => {
+                report.failed.push(fail("路径信息格式不正确"));
+                continue;
+            }
+        };
         // let path_str = path_str
         //     .replace(" ", "_")
         //     .replace("\\", "")
@@ -289,7 +582,9 @@ pub(crate) async fn db_handler(cx: &UpdateWithCx<Bot, Message>, doc: &Document)
         //     .0;
 
         content.push_str(&format!("{}|{}|{}\n", head, preid, path_str));
+        report.decrypted += 1;
     }
+    report.form = decoder.detected();
 
     let filename = doc
         .file_name
@@ -312,6 +607,12 @@ pub(crate) async fn db_handler(cx: &UpdateWithCx<Bot, Message>, doc: &Document)
 
     let output_path = format!("{}/{}", ROOT_FOLDER, new_filename);
     let output_path = Path::new(&output_path);
+    let failed_path = format!(
+        "{}/{}_解密失败.txt",
+        ROOT_FOLDER,
+        new_filename.strip_suffix(".txt").unwrap()
+    );
+    let failed_path = Path::new(&failed_path);
     defer! {
         if db_path.exists() {
             let _ = remove_file(&db_path);
@@ -319,78 +620,24 @@ pub(crate) async fn db_handler(cx: &UpdateWithCx<Bot, Message>, doc: &Document)
         if output_path.exists(){
             let _ = remove_file(output_path);
         }
+        if failed_path.exists(){
+            let _ = remove_file(failed_path);
+        }
     }
 
-    let summary = sqlx::query(
-    r#"
-    SELECT SUM(FILESIZE), MAX(FILESIZE), MIN(FILESIZE), COUNT(FILESIZE) FROM "myfiles" WHERE SHA1!=0 AND PREID!=0 AND PREID!='error'AND SHA1!='error' ORDER BY "SNO";
-"#).fetch_one(&pool).await?;
-
-    let total_size = summary.try_get::<i64, usize>(0)?.try_into()?;
-    let max = summary.try_get::<i64, usize>(1)?.try_into()?;
-    let min = summary.try_get::<i64, usize>(2)?.try_into()?;
-    let total_files = summary.try_get::<i64, usize>(3)?.try_into()?;
-    let mid = sqlx::query(
-        r#"
-SELECT
-        AVG(FILESIZE)
-FROM (
-        SELECT
-                FILESIZE
-        FROM
-                "myfiles"
-        WHERE
-                SHA1 != 0
-                AND PREID != 0
-                AND PREID != 'error'
-                AND SHA1 != 'error'
-        ORDER BY
-                FILESIZE
-        LIMIT 2 - (
-                SELECT
-                        COUNT(*)
-                FROM
-                        "myfiles"
-                WHERE
-                        SHA1 != 0
-                        AND PREID != 0
-                        AND PREID != 'error'
-                        AND SHA1 != 'error') % 2 -- odd 1, even 2
-                OFFSET (
-                        SELECT
-                                (COUNT(*) - 1) / 2
-                        FROM
-                                "myfiles"
-                        WHERE
-                                SHA1 != 0
-                                AND PREID != 0
-                                AND PREID != 'error'
-                                AND SHA1 != 'error'))
-              "#,
-    )
-    .fetch_one(&pool)
-    .await?
-    .try_get::<f64, usize>(0)?;
-
-    let summary = Summary {
-        total_size,
-        max,
-        min,
-        mid,
-        total_files,
-        has_folder: true,
-        encrypted: false,
-    };
-
     if !content.is_empty() {
         let _ = copied(bot, msg).await;
+        // the same numbers as for the exported lines
+        let summary = line_summary_mem(&content)?;
         write_all_to_file(output_path, content.as_bytes()).await?;
-        let input_file = InputFile::File(output_path.to_path_buf());
-        let mut req = cx.requester.send_document(msg.chat_id(), input_file);
-        let payload = req.payload_mut();
-        payload.reply_to_message_id = Some(msg.id);
-        payload.caption = Some(summary.to_string());
-        req.await?;
+        let caption = format!("{}\n{}", report, summary.for_chat(msg.chat.is_private()));
+        reply_document_to(cx, output_path, msg, Some(caption)).await?;
+    } else if !report.failed.is_empty() {
+        cx.reply_to(report.to_string()).await?;
+    }
+    if !report.failed.is_empty() {
+        write_all_to_file(failed_path, report.details().as_bytes()).await?;
+        reply_document_to(cx, failed_path, msg, None).await?;
     }
 
     pool.close().await;
@@ -558,7 +805,7 @@ async fn w_magnet(cx: &UpdateWithCx<Bot, Message>, replied_msg: &Message) -> Res
     } else {
         let mut res = String::new();
         list.iter()
-            .for_each(|hash| res.push_str(&format!("magnet:?xt=urn:btih:{}\n", hash)));
+            .for_each(|urn| res.push_str(&format!("magnet:?xt=urn:{}\n", urn)));
         write_all_to_file(output_path, res.as_bytes()).await?;
     }
 
@@ -581,11 +828,7 @@ async fn f_encoding(cx: &UpdateWithCx<Bot, Message>, replied_msg: &Message) -> R
     }
 
     let res = file_encoding(&target_file_path).await?;
-    let rep = if res.trim() == "unknown" {
-        "看不出来啥编码...".to_owned()
-    } else {
-        format!("编码可能是：{}", res)
-    };
+    let rep = format!("编码可能是：{}", res);
 
     let msg_to_del = cx.reply_to(rep).await?;
     sleep(Duration::from_secs(30)).await;
@@ -677,20 +920,577 @@ pub(crate) async fn command_check(cx: &UpdateWithCx<Bot, Message>, text: &str) -
     Ok(())
 }
 
-async fn link_check(cx: &UpdateWithCx<Bot, Message>, text: &str) -> Result<()> {
-    lazy_static! {
-        static ref SHA1RE: Regex =
-            Regex::new(r"115://(.*?)\|(\d*?)(?:\|[a-fA-F0-9]{40}){2}").unwrap();
+/// lists collected with `'set add` are dropped this long after the last one was added
+const PENDING_LISTS_TTL: Duration = Duration::from_secs(60 * 60);
+/// lists one user can collect with `'set add`
+const MAX_PENDING_LISTS: usize = 10;
+
+struct PendingLists {
+    added: Instant,
+    docs: Vec<Document>,
+}
+
+lazy_static! {
+    /// lists collected with `'set add`, per chat and user
+    static ref PENDING_LISTS: Mutex<HashMap<(i64, i64), PendingLists>> =
+        Mutex::new(HashMap::new());
+}
+
+/// the lists of every user, stale ones dropped first
+fn pending_lists() -> MutexGuard<'static, HashMap<(i64, i64), PendingLists>> {
+    let mut pending = PENDING_LISTS.lock().unwrap();
+    pending.retain(|_, lists| lists.added.elapsed() < PENDING_LISTS_TTL);
+    pending
+}
+
+fn push_list(docs: &mut Vec<Document>, doc: &Document) {
+    if !docs.iter().any(|d| d.file_unique_id == doc.file_unique_id) {
+        docs.push(doc.clone());
+    }
+}
+
+/// `'set add|clear|union|inter|diff|xor`, operates on the lists added before,
+/// the replied one and the one the command is the caption of, in that order.
+/// returns whether the message was a set command
+async fn set_check(cx: &UpdateWithCx<Bot, Message>, text: &str) -> Result<bool> {
+    let msg = &cx.update;
+    let arg = match text.trim().strip_prefix("'set") {
+        Some(arg) => arg.trim(),
+        None => return Ok(false),
+    };
+    let key = (
+        msg.chat.id,
+        msg.from().map(|user| user.id).unwrap_or_default(),
+    );
+    let replied_doc = msg.reply_to_message().and_then(|m| m.document());
+
+    match arg {
+        "add" | "加入" => {
+            let doc = match command_document(msg) {
+                Some(doc) => doc,
+                None => return Ok(true),
+            };
+            let count = {
+                let mut pending = pending_lists();
+                let lists = pending.entry(key).or_insert_with(|| PendingLists {
+                    added: Instant::now(),
+                    docs: Vec::new(),
+                });
+                lists.added = Instant::now();
+                if lists.docs.len() < MAX_PENDING_LISTS {
+                    push_list(&mut lists.docs, doc);
+                    Some(lists.docs.len())
+                } else {
+                    None
+                }
+            };
+            match count {
+                Some(count) => {
+                    cx.reply_to(format!("已加入, 当前共 {} 个列表", count))
+                        .await?
+                }
+                None => {
+                    cx.reply_to(format!(
+                        "最多加入 {} 个列表, 请先进行集合运算或发送 'set clear",
+                        MAX_PENDING_LISTS
+                    ))
+                    .await?
+                }
+            };
+        }
+        "clear" | "清空" => {
+            pending_lists().remove(&key);
+            cx.reply_to("已清空").await?;
+        }
+        op => {
+            let op: SetOp = match op.parse() {
+                Ok(op) => op,
+                Err(_) => return Ok(false),
+            };
+            let mut docs = pending_lists()
+                .remove(&key)
+                .map(|lists| lists.docs)
+                .unwrap_or_default();
+            for doc in [replied_doc, msg.document()].into_iter().flatten() {
+                push_list(&mut docs, doc);
+            }
+            if docs.len() < 2 {
+                cx.reply_to("至少需要两个列表, 可以先回复列表发送 'set add")
+                    .await?;
+                return Ok(true);
+            }
+            set_reply(cx, op, &docs).await?;
+        }
     }
+    Ok(true)
+}
+
+async fn set_reply(cx: &UpdateWithCx<Bot, Message>, op: SetOp, docs: &[Document]) -> Result<()> {
+    let output_path = format!(
+        "{}/{}_{}.txt",
+        ROOT_FOLDER,
+        op,
+        BASE32_NOPAD.encode(&Utc::now().timestamp().to_ne_bytes())
+    );
+    let output_path = Path::new(&output_path);
 
+    let mut inputs = Vec::new();
+    let mut failed = None;
+    for doc in docs {
+        match download_file(&cx.requester, doc).await {
+            Ok(path) => inputs.push(ListInput {
+                name: doc
+                    .file_name
+                    .to_owned()
+                    .unwrap_or_else(|| "default_name".to_owned()),
+                path,
+            }),
+            Err(e) => {
+                failed = Some(e);
+                break;
+            }
+        }
+    }
+    defer! {
+        for input in &inputs {
+            let _ = remove_file(&input.path);
+        }
+        if output_path.exists(){
+            let _ = remove_file(output_path);
+        }
+    }
+    if let Some(e) = failed {
+        return Err(e);
+    }
+
+    let report = set_operation(op, &inputs, output_path).await?;
+    if report.is_empty() {
+        cx.reply_to(report.to_string()).await?;
+    } else {
+        reply_document_to(cx, output_path, &cx.update, Some(report.to_string())).await?;
+    }
+    Ok(())
+}
+
+/// the replied list, or the one the command is the caption of
+fn command_document(msg: &Message) -> Option<&Document> {
+    msg.reply_to_message()
+        .and_then(|m| m.document())
+        .or_else(|| msg.document())
+}
+
+/// repairs a mangled list and replies with it and what changed, returns whether anything was fixed
+async fn reply_repaired(
+    cx: &UpdateWithCx<Bot, Message>,
+    doc: &Document,
+    path: &Path,
+) -> Result<bool> {
+    let msg = &cx.update;
+    let filename = doc
+        .file_name
+        .to_owned()
+        .unwrap_or_else(|| "default_name".to_owned());
+    let stem = match filename.rsplit_once('.') {
+        Some((stem, _)) => stem.to_owned(),
+        None => filename.to_owned(),
+    };
+    let timestamp = BASE32_NOPAD.encode(&Utc::now().timestamp().to_ne_bytes());
+    let output_path = Path::new(ROOT_FOLDER).join(format!("{}_已修复格式_{}.txt", stem, timestamp));
+    let log_path = Path::new(ROOT_FOLDER).join(format!("{}_修复记录_{}.txt", stem, timestamp));
+    defer! {
+        if output_path.exists() {
+            let _ = remove_file(&output_path);
+        }
+        if log_path.exists() {
+            let _ = remove_file(&log_path);
+        }
+    }
+
+    let report = repair_file(path, &output_path, &log_path).await?;
+    if report.is_empty() || report.valid == 0 {
+        return Ok(false);
+    }
+    reply_document_to(cx, &output_path, msg, Some(report.to_string())).await?;
+    reply_document_to(cx, &log_path, msg, None).await?;
+    Ok(true)
+}
+
+/// `'repair`, returns whether the message was a repair command
+async fn repair_check(cx: &UpdateWithCx<Bot, Message>, text: &str) -> Result<bool> {
+    let msg = &cx.update;
+    if text.trim() != "'repair" {
+        return Ok(false);
+    }
+    let doc = match command_document(msg) {
+        Some(doc) => doc,
+        None => return Ok(true),
+    };
+
+    let path = download_file(&cx.requester, doc).await?;
+    defer! {
+        if path.exists() {
+            let _ = remove_file(&path);
+        }
+    }
+    if !reply_repaired(cx, doc, &path).await? {
+        cx.reply_to("没有发现可以自动修复的问题").await?;
+    }
+    Ok(true)
+}
+
+/// `'split 1000`, `'split 2TB` or `'split folder`, returns whether the message was a split command
+async fn split_check(cx: &UpdateWithCx<Bot, Message>, text: &str) -> Result<bool> {
+    let msg = &cx.update;
+    let mode: SplitMode = match text.trim().strip_prefix("'split") {
+        Some(arg) => match arg.trim().parse() {
+            Ok(mode) => mode,
+            Err(_) => return Ok(false),
+        },
+        None => return Ok(false),
+    };
+    let doc = match command_document(msg) {
+        Some(doc) => doc,
+        None => return Ok(true),
+    };
+
+    let filename = doc
+        .file_name
+        .to_owned()
+        .unwrap_or_else(|| "default_name".to_owned());
+    let stem = match filename.rsplit_once('.') {
+        Some((stem, _)) => stem.to_owned(),
+        None => filename.to_owned(),
+    };
+    let out_dir = Path::new(ROOT_FOLDER).join(format!(
+        "split_{}",
+        BASE32_NOPAD.encode(&Utc::now().timestamp_millis().to_ne_bytes())
+    ));
+    let zip_path = out_dir.join(format!("{}_拆分.zip", stem));
+
+    let path = download_file(&cx.requester, doc).await?;
+    defer! {
+        if path.exists() {
+            let _ = remove_file(&path);
+        }
+        if out_dir.exists() {
+            let _ = std::fs::remove_dir_all(&out_dir);
+        }
+    }
+    std::fs::create_dir_all(&out_dir)?;
+
+    let report = split_list(&path, mode, &out_dir, &stem).await?;
+    if report.parts.len() > ZIP_THRESHOLD {
+        zip_parts(&report.parts, &zip_path)?;
+        reply_document_to(cx, &zip_path, msg, Some(report.to_string())).await?;
+    } else {
+        cx.reply_to(report.to_string()).await?;
+        for part in &report.parts {
+            reply_document_to(cx, &part.path, msg, None).await?;
+        }
+    }
+    Ok(true)
+}
+
+/// `'dup` reports renamed copies and conflicting versions, `'dup <policy>` keeps one per group,
+/// returns whether the message was a dup command
+async fn dup_check(cx: &UpdateWithCx<Bot, Message>, text: &str) -> Result<bool> {
+    let msg = &cx.update;
+    let policy: Option<KeepPolicy> = match text.trim().strip_prefix("'dup") {
+        Some("") => None,
+        Some(arg) if arg.starts_with(' ') => match arg.trim().parse() {
+            Ok(policy) => Some(policy),
+            Err(_) => return Ok(false),
+        },
+        _ => return Ok(false),
+    };
+    let doc = match command_document(msg) {
+        Some(doc) => doc,
+        None => return Ok(true),
+    };
+
+    let filename = doc
+        .file_name
+        .to_owned()
+        .unwrap_or_else(|| "default_name".to_owned());
+    let stem = match filename.rsplit_once('.') {
+        Some((stem, _)) => stem.to_owned(),
+        None => filename.to_owned(),
+    };
+    let timestamp = BASE32_NOPAD.encode(&Utc::now().timestamp().to_ne_bytes());
+
+    let path = download_file(&cx.requester, doc).await?;
+    defer! {
+        if path.exists() {
+            let _ = remove_file(&path);
+        }
+    }
+    let (records, format) = load_list(&path).await?;
+    let conflicts = find_conflicts(&records);
+    if conflicts.is_empty() {
+        cx.reply_to("没有发现改名的重复文件或同名不同内容的文件")
+            .await?;
+        return Ok(true);
+    }
+
+    let output_path = match policy {
+        Some(_) => Path::new(ROOT_FOLDER).join(format!(
+            "{}_去冲突_{}.{}",
+            stem,
+            timestamp,
+            list_extension(format)
+        )),
+        None => Path::new(ROOT_FOLDER).join(format!("{}_冲突报告_{}.txt", stem, timestamp)),
+    };
+    defer! {
+        if output_path.exists() {
+            let _ = remove_file(&output_path);
+        }
+    }
+
+    let caption = match policy {
+        Some(policy) => {
+            let (kept, dropped) = resolve_conflicts(records, policy);
+            write_all_to_file(&output_path, &list_bytes(kept, format)?).await?;
+            format!("{}\n{}, 共删除 {} 个文件", conflicts, policy, dropped)
+        }
+        None => {
+            write_all_to_file(&output_path, conflicts.details(&records).as_bytes()).await?;
+            conflicts.to_string()
+        }
+    };
+    reply_document_to(cx, &output_path, msg, Some(caption)).await?;
+    Ok(true)
+}
+
+/// results up to this many are listed in the chat instead of sent as a file
+const FILTER_LISTING_LIMIT: usize = 30;
+
+/// `'filter <conditions>`, returns whether the message was a filter command
+async fn filter_check(cx: &UpdateWithCx<Bot, Message>, text: &str) -> Result<bool> {
+    let msg = &cx.update;
+    let filter: Filter = match text.trim().strip_prefix("'filter") {
+        Some(arg) => match arg.parse() {
+            Ok(filter) => filter,
+            Err(e) => {
+                cx.reply_to(format!("筛选条件有误: {}", e)).await?;
+                return Ok(true);
+            }
+        },
+        None => return Ok(false),
+    };
+    let doc = match command_document(msg) {
+        Some(doc) => doc,
+        None => return Ok(true),
+    };
+
+    let filename = doc
+        .file_name
+        .to_owned()
+        .unwrap_or_else(|| "default_name".to_owned());
+    let stem = match filename.rsplit_once('.') {
+        Some((stem, _)) => stem.to_owned(),
+        None => filename.to_owned(),
+    };
+
+    let path = download_file(&cx.requester, doc).await?;
+    defer! {
+        if path.exists() {
+            let _ = remove_file(&path);
+        }
+    }
+
+    let (records, format) = load_list(&path).await?;
+    let total = records.len();
+    let matched: Vec<_> = records.into_iter().filter(|r| filter.matches(r)).collect();
+    let size: u64 = matched.iter().map(|r| r.file.size()).sum();
+    let summary = format!(
+        "{}\n{} 个文件中筛选出 {} 个, 总计: {}",
+        filter,
+        total,
+        matched.len(),
+        to_iec(size)
+    );
+
+    if matched.is_empty() {
+        cx.reply_to(summary).await?;
+    } else if matched.len() <= FILTER_LISTING_LIMIT {
+        let mut response = String::new();
+        for record in &matched {
+            let full_path = [record.folders.join("/"), record.file.name().to_owned()].join("/");
+            response.push_str(&format!(
+                "{:>7} => {}\n",
+                to_iec(record.file.size()),
+                escape(&full_path)
+            ));
+        }
+        let mut req = cx.reply_to(format!("<code>{}</code>{}", response, escape(&summary)));
+        let payload = req.payload_mut();
+        payload.parse_mode = Some(ParseMode::Html);
+        req.await?;
+    } else {
+        let output_path = Path::new(ROOT_FOLDER).join(format!(
+            "{}_筛选_{}.{}",
+            stem,
+            BASE32_NOPAD.encode(&Utc::now().timestamp().to_ne_bytes()),
+            list_extension(format)
+        ));
+        defer! {
+            if output_path.exists() {
+                let _ = remove_file(&output_path);
+            }
+        }
+        write_all_to_file(&output_path, &list_bytes(matched, format)?).await?;
+        reply_document_to(cx, &output_path, msg, Some(summary)).await?;
+    }
+    Ok(true)
+}
+
+/// `'tree strip 1, prune, sort size`, returns whether the message was a tree command
+async fn tree_check(cx: &UpdateWithCx<Bot, Message>, text: &str) -> Result<bool> {
+    let msg = &cx.update;
+    let ops = match text.trim().strip_prefix("'tree") {
+        Some(arg) => match parse_tree_ops(arg) {
+            Ok(ops) => ops,
+            Err(e) => {
+                cx.reply_to(format!("目录操作有误: {}", e)).await?;
+                return Ok(true);
+            }
+        },
+        None => return Ok(false),
+    };
+    let doc = match command_document(msg) {
+        Some(doc) => doc,
+        None => return Ok(true),
+    };
+
+    let filename = doc
+        .file_name
+        .to_owned()
+        .unwrap_or_else(|| "default_name".to_owned());
+    let stem = match filename.rsplit_once('.') {
+        Some((stem, _)) => stem.to_owned(),
+        None => filename.to_owned(),
+    };
+
+    let path = download_file(&cx.requester, doc).await?;
+    defer! {
+        if path.exists() {
+            let _ = remove_file(&path);
+        }
+    }
+
+    let (mut entity, format) = load_entity(&path).await?;
+    for op in &ops {
+        entity = op.apply(entity)?;
+    }
+
+    let output_path = Path::new(ROOT_FOLDER).join(format!(
+        "{}_{}.{}",
+        stem,
+        BASE32_NOPAD.encode(&Utc::now().timestamp().to_ne_bytes()),
+        list_extension(format)
+    ));
+    defer! {
+        if output_path.exists() {
+            let _ = remove_file(&output_path);
+        }
+    }
+    write_all_to_file(&output_path, &entity_bytes(&entity, format)?).await?;
+
+    let caption = ops
+        .iter()
+        .map(|op| op.to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ");
+    reply_document_to(cx, &output_path, msg, Some(caption)).await?;
+    Ok(true)
+}
+
+/// `'du [depth] [file]`, returns whether the message was a du command
+async fn du_check(cx: &UpdateWithCx<Bot, Message>, text: &str) -> Result<bool> {
+    let msg = &cx.update;
+    let args: Vec<&str> = match text.trim().strip_prefix("'du") {
+        Some(arg) if arg.is_empty() || arg.starts_with(' ') => arg.split_whitespace().collect(),
+        _ => return Ok(false),
+    };
+    let mut depth = DU_REPORT_DEPTH;
+    let mut as_file = false;
+    for arg in args {
+        match arg {
+            "file" | "文件" => as_file = true,
+            _ => match arg.parse() {
+                Ok(n) => depth = n,
+                Err(_) => return Ok(false),
+            },
+        }
+    }
+    let doc = match command_document(msg) {
+        Some(doc) => doc,
+        None => return Ok(true),
+    };
+
+    let filename = doc
+        .file_name
+        .to_owned()
+        .unwrap_or_else(|| "default_name".to_owned());
+    let stem = match filename.rsplit_once('.') {
+        Some((stem, _)) => stem.to_owned(),
+        None => filename.to_owned(),
+    };
+
+    let path = download_file(&cx.requester, doc).await?;
+    defer! {
+        if path.exists() {
+            let _ = remove_file(&path);
+        }
+    }
+    let (entity, _) = load_entity(&path).await?;
+
+    if as_file {
+        let output_path = Path::new(ROOT_FOLDER).join(format!(
+            "{}_目录大小_{}.txt",
+            stem,
+            BASE32_NOPAD.encode(&Utc::now().timestamp().to_ne_bytes())
+        ));
+        defer! {
+            if output_path.exists() {
+                let _ = remove_file(&output_path);
+            }
+        }
+        write_all_to_file(&output_path, du_report(&entity, depth).as_bytes()).await?;
+        reply_document_to(cx, &output_path, msg, None).await?;
+    } else {
+        let mut req = cx.reply_to(format!(
+            "<code>{}</code>",
+            escape(&du_table(&entity, DU_TABLE_ROWS))
+        ));
+        let payload = req.payload_mut();
+        payload.parse_mode = Some(ParseMode::Html);
+        req.await?;
+    }
+    Ok(true)
+}
+
+async fn link_check(cx: &UpdateWithCx<Bot, Message>, text: &str) -> Result<()> {
     let mut response: String = Default::default();
     let mut counter = 0;
     let mut sum: u128 = 0;
-    for cap in SHA1RE.captures_iter(text) {
-        counter += 1;
-        let size: u128 = cap[2].parse()?;
-        sum += size;
-        response.push_str(&format!("{:>7} => {}\n", to_iec(size), &cap[1]));
+    for (i, line) in text.lines().enumerate() {
+        let start = if let Some(start) = line.find("115://") {
+            start
+        } else {
+            continue;
+        };
+        if let Ok(Some(record)) = parse_line(&line[start..], i + 1) {
+            counter += 1;
+            let size = record.file.size() as u128;
+            sum += size;
+            response.push_str(&format!(
+                "{:>7} => {}\n",
+                to_iec(size),
+                escape(record.file.name())
+            ));
+        }
     }
 
     match counter {
@@ -713,31 +1513,41 @@ async fn link_check(cx: &UpdateWithCx<Bot, Message>, text: &str) -> Result<()> {
 }
 
 async fn magnet_check(cx: &UpdateWithCx<Bot, Message>, text: &str) -> Result<()> {
-    lazy_static! {
-        static ref MAGNET_RE: Regex =
-            Regex::new(r"magnet:\?xt=urn:btih:([a-fA-F0-9]{40}|[a-zA-Z2-7]{32})").unwrap();
-    }
-    let mut reply: String = Default::default();
-    let hash: String;
-    let mut iter = MAGNET_RE.captures_iter(text);
-    if let Some(m) = iter.next() {
-        if m[1].len() == 40 {
-            hash = m[1].to_string();
-        } else if m[1].len() == 32 {
-            hash = base32_hex(&m[1])?;
-        } else {
-            unreachable!();
-        }
-    } else {
-        return Ok(());
-    }
-
-    if iter.next().is_some() {
+    let mut links = all_magnet_links(text);
+    if links.len() != 1 {
         // ignore more than one magnet
         return Ok(());
     }
+    let magnet = links.remove(0);
+
+    let reply = if magnet.is_self_describing() {
+        // everything needed is in the link, no need to ask the torrent cache
+        format!(
+            "<code>{}</code>\n---\n{}",
+            escape(magnet.display_name.as_deref().unwrap_or("")),
+            magnet.summary()
+        )
+    } else if let Some(hash) = &magnet.btih {
+        match magnet_info(hash).await {
+            Ok(info) => info,
+            Err(e) => {
+                let name = if let Some(name) = &magnet.display_name {
+                    name
+                } else {
+                    return Err(e);
+                };
+                log::warn!("magnet_info failed, fall back to dn: {}", e);
+                format!("<code>{}</code>\n---\n{}", escape(name), magnet.summary())
+            }
+        }
+    } else {
+        // v2 only magnet, the torrent cache only knows v1 infohashes
+        format!(
+            "v2 磁力链接, sha256: {}\n暂时无法查询种子信息",
+            magnet.btmh.as_deref().unwrap_or("")
+        )
+    };
 
-    reply.push_str(&magnet_info(&hash).await?);
     let mut request = cx.reply_to(reply);
     let payload = request.payload_mut();
     payload.parse_mode = Some(teloxide::types::ParseMode::Html);
@@ -754,15 +1564,13 @@ async fn version(cx: &UpdateWithCx<Bot, Message>) -> Result<()> {
 }
 
 async fn help(cx: &UpdateWithCx<Bot, Message>) -> Result<()> {
-    cx.requester.send_message(cx.update.chat_id(), HELP).await?;
+    let help = HELP.replace("{max_file_size}", &to_iec(unsafe { MAX_FILE_SIZE }));
+    cx.requester.send_message(cx.update.chat_id(), help).await?;
     Ok(())
 }
 
 pub(crate) async fn message_handler(cx: UpdateWithCx<Bot, Message>) -> Result<()> {
-    let UpdateWithCx {
-        requester: bot,
-        update: msg,
-    } = &cx;
+    let UpdateWithCx { update: msg, .. } = &cx;
     // log::info!("getting a msg!!");
 
     // if let teloxide::types::MessageKind::NewChatMembers(member) = &msg.kind {
@@ -788,19 +1596,32 @@ pub(crate) async fn message_handler(cx: UpdateWithCx<Bot, Message>) -> Result<()
         link_check(&cx, text).await?;
         magnet_check(&cx, text).await?;
         command_check(&cx, text).await?;
+        if set_check(&cx, text).await?
+            || split_check(&cx, text).await?
+            || filter_check(&cx, text).await?
+            || tree_check(&cx, text).await?
+            || du_check(&cx, text).await?
+            || dup_check(&cx, text).await?
+            || repair_check(&cx, text).await?
+        {
+            return Ok(());
+        }
         // spam_check_dummy(&cx, text).await?;
     }
 
     if let Some(doc) = msg.document() {
         if let Some(size) = &doc.file_size {
-            if *size > 1024 * 1024 * 20 {
+            if *size as u64 > unsafe { MAX_FILE_SIZE } {
                 //ignore
                 return Ok(());
             }
         }
 
         if let Some(doc_type) = &doc.mime_type {
-            if doc
+            if ArchiveKind::from_name(doc.file_name.as_ref().unwrap_or(&"".to_string())).is_some() {
+                log::info!("getting an archive");
+                archive_handler(&cx, doc).await?;
+            } else if doc
                 .file_name
                 .as_ref()
                 .unwrap_or(&"".to_string())
@@ -809,6 +1630,18 @@ pub(crate) async fn message_handler(cx: UpdateWithCx<Bot, Message>) -> Result<()
             {
                 log::info!("getting a db");
                 db_handler(&cx, doc).await?;
+            } else if *doc_type == "text/csv"
+                || *doc_type == "text/tab-separated-values"
+                || [".csv", ".tsv"].iter().any(|ext| {
+                    doc.file_name
+                        .as_ref()
+                        .unwrap_or(&"".to_string())
+                        .to_lowercase()
+                        .ends_with(ext)
+                })
+            {
+                log::info!("getting a csv");
+                csv_handler(&cx, doc).await?;
             } else if *doc_type == mime::TEXT_PLAIN
                 || doc
                     .file_name
@@ -831,18 +1664,7 @@ pub(crate) async fn message_handler(cx: UpdateWithCx<Bot, Message>) -> Result<()
                 json_handler(&cx, doc).await?;
             } else if *doc_type == "application/x-bittorrent" {
                 log::info!("getting a torrent");
-                let path = download_file(bot, doc).await?;
-                defer! { let _ = std::fs::remove_file(&path); }
-
-                let reply = format!(
-                    "<code>{}</code>\n---\n总计: {}",
-                    get_torrent_magnet_async(&path).await?,
-                    get_torrent_summary_async(&path).await?
-                );
-                let mut request = cx.reply_to(reply);
-                let payload = request.payload_mut();
-                payload.parse_mode = Some(teloxide::types::ParseMode::Html);
-                request.await?;
+                torrent_handler(&cx, doc).await?;
             }
         }
     }
@@ -1,50 +1,90 @@
 use anyhow::{anyhow, Result};
 use anyhow::{bail, Context};
+use crypto::digest::Digest;
 use crypto::md5::Md5;
-use crypto::{digest::Digest, sha1::Sha1};
-use data_encoding::{BASE32_NOPAD, HEXUPPER};
+use data_encoding::{BASE32_NOPAD, HEXLOWER, HEXUPPER, HEXUPPER_PERMISSIVE};
 use lazy_static::lazy_static;
-use magnet_url::Magnet;
 use pakr_iec::iec;
 use regex::Regex;
-use scopeguard::defer;
 use serde::{Deserialize, Deserializer, Serialize};
-use serde_bytes::ByteBuf;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 
 use std::path::Path;
-use std::process::Command;
 use std::str::FromStr;
+use teloxide::utils::html::escape;
 use tokio::fs::File as TokioFile;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufWriter};
 
-pub(crate) async fn json2line(input: &Path, output: &Path) -> Result<()> {
+/// `folder_lines` also writes a `||||folder` line for each empty folder, which other tools can't
+/// read
+pub(crate) async fn json2line(input: &Path, output: &Path, folder_lines: bool) -> Result<()> {
     check_input_output(input, output).await?;
 
-    let mut file = open_without_bom(input).await?;
-    let mut buf: Vec<u8> = Vec::new();
-    file.read_to_end(&mut buf).await?;
-    file.flush().await?;
-    let entity: Sha1Entity = serde_json::from_slice(&buf)?;
-    let file = std::fs::File::create(output)?;
-    let mut writer = std::io::BufWriter::new(file);
-    write_line(&mut writer, &entity, "".to_owned());
-    Ok(())
+    let content = read_utf8(input).await?;
+    let (entity, _) = parse_sha1_json(content.as_bytes())?;
+    write_all_to_file(output, entity_lines(&entity, folder_lines).as_bytes()).await
 }
 
-pub(crate) fn json2line_mem(entity: &Sha1Entity) -> Result<String> {
-    let mut res = String::new();
-    write_line_mem(&mut res, entity, "".to_owned());
-    Ok(res)
+/// lines of a tree and where they were in the list the tree came from
+type PlacedLines = Vec<(Option<u64>, String)>;
+
+/// a file and the extra fields it came with, `path` is empty or starts with `|`
+fn push_file_line(lines: &mut PlacedLines, file: &FileRepr, path: &str) {
+    let mut line = file.to_sha1_link() + path;
+    if !file.extra().is_empty() {
+        line.push_str("||");
+        line.push_str(&file.extra().join("|"));
+    }
+    lines.push((file.line_number(), line));
+}
+
+fn push_tree_lines(lines: &mut PlacedLines, entity: &Sha1Entity, parent: &str, folder_lines: bool) {
+    let path = if entity.dir_name.is_empty() {
+        parent.to_owned()
+    } else {
+        format!("{}|{}", parent, entity.dir_name)
+    };
+    let empty = entity.files.is_empty() && entity.dirs.is_empty();
+    if folder_lines && !path.is_empty() && (empty || entity.line_number.is_some()) {
+        lines.push((
+            entity.line_number,
+            format!("{}{}", FOLDER_LINE_PREFIX, &path[1..]),
+        ));
+    }
+    for file in &entity.files {
+        push_file_line(lines, file, &path);
+    }
+    for dir in &entity.dirs {
+        push_tree_lines(lines, dir, &path, folder_lines);
+    }
+}
+
+/// the lines of a tree, the synthetic parent and folders without a name add nothing to the path,
+/// `folder_lines` also writes the empty folders. lines that know where they were in the list the
+/// tree came from go back there, the rest follow in tree order
+pub(crate) fn entity_lines(entity: &Sha1Entity, folder_lines: bool) -> String {
+    let (roots, files) = top_level(entity);
+    let mut lines = PlacedLines::new();
+    for file in files {
+        push_file_line(&mut lines, file, "");
+    }
+    for root in roots {
+        push_tree_lines(&mut lines, root, "", folder_lines);
+    }
+    lines.sort_by_key(|(line_number, _)| line_number.unwrap_or(u64::MAX));
+    let mut content = String::new();
+    for (_, line) in lines {
+        content.push_str(&line);
+        content.push('\n');
+    }
+    content
 }
 
 pub(crate) async fn line_strip_dir_info(input: &Path, output: &Path) -> Result<()> {
     check_input_output(input, output).await?;
 
-    let file = open_without_bom(input).await?;
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines();
+    let mut reader = Sha1LineReader::open(input).await?;
 
     let out_file = TokioFile::create(output).await.context(format!(
         "failed to create the output file:{}",
@@ -53,18 +93,15 @@ pub(crate) async fn line_strip_dir_info(input: &Path, output: &Path) -> Result<(
 
     let mut writer = BufWriter::new(out_file);
 
-    while let Some(line) = lines.next_line().await.context(format!(
-        "failed to read line from file: {}",
-        input.to_string_lossy()
-    ))? {
-        let repr = match FileRepr::from_str(&line) {
-            Ok(file) => file,
-            Err(_) => {
-                log::warn!("invalid line during stripping dir info: {}", line);
+    while let Some(parsed) = reader.next_line().await? {
+        let record = match parsed {
+            Ok(record) => record,
+            Err(rejected) => {
+                log::warn!("invalid line during stripping dir info: {}", rejected);
                 continue;
             }
         };
-        let line = repr.to_sha1_link() + "\n";
+        let line = record.file.to_sha1_link() + "\n";
         writer.write_all(line.as_bytes()).await?;
     }
     writer.flush().await?;
@@ -75,9 +112,7 @@ pub(crate) async fn line_strip_dir_info(input: &Path, output: &Path) -> Result<(
 pub(crate) async fn is_valid_line(input: &Path) -> Result<()> {
     check_input(input).await?;
 
-    let file = open_without_bom(input).await?;
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines();
+    let mut lines = open_utf8(input).await?.lines();
 
     if let Some(line) = lines.next_line().await.context(format!(
         "failed to read line from file: {}",
@@ -96,101 +131,220 @@ pub(crate) async fn is_valid_line(input: &Path) -> Result<()> {
     Err(WrongSha1LinkFormat.into())
 }
 
-pub(crate) async fn line2json(input: &Path, output: &Path) -> Result<()> {
+/// `preserve` keeps lines without folders in a root without a name, reads empty folder lines,
+/// writes several roots as an array and notes where each line was, so `json2line` can give the
+/// lines back in their order, otherwise several roots go under a synthetic parent, folders keep the
+/// order they first show up in either way
+pub(crate) async fn line2json(input: &Path, output: &Path, preserve: bool) -> Result<()> {
     check_input_output(input, output).await?;
 
-    let mut root_list: Vec<Sha1Entity> = Vec::new();
-    let file = open_without_bom(input).await?;
-
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines();
-
-    while let Some(line) = lines.next_line().await.context(format!(
-        "failed to read line from file: {}",
-        input.to_string_lossy()
-    ))? {
-        let parts: Vec<&str> = line.split('|').collect();
-
-        let repr = match FileRepr::from_str(&line) {
-            Ok(file) => file,
-            Err(_) => {
-                log::warn!("invalid line during stripping dir info: {}", line);
-                continue;
+    // each line goes into the tree as it is read, only the files stay in memory
+    let mut tree = if preserve {
+        TreeBuilder::keeping_loose_files()
+    } else {
+        TreeBuilder::default()
+    };
+    let mut reader = Sha1LineReader::open(input).await?;
+    while let Some(item) = reader.next_item().await? {
+        match item {
+            LineItem::File(Ok(mut record)) => {
+                if preserve {
+                    record.file.set_line_number(Some(record.line_number as u64));
+                }
+                tree.push_line(record)
             }
-        };
-
-        let mut curr_list: &mut Vec<_> = &mut root_list;
-        for part in parts.iter().take(parts.len() - 1).skip(4) {
-            let folder = get_dir_or_create(part, curr_list);
-            curr_list = &mut folder.dirs;
+            LineItem::File(Err(rejected)) => {
+                log::warn!("invalid line during line2json: {}", rejected)
+            }
+            LineItem::Folder(path) if preserve => {
+                tree.push_folder(&path, Some(reader.line_number() as u64))
+            }
+            LineItem::Folder(_) => {}
         }
-        let folder = get_dir_or_create(parts[parts.len() - 1], curr_list);
-        folder.files.push(repr);
     }
 
-    // if list contains multiple entities, create a parent folder for them
-
-    let out_file = TokioFile::create(output).await.context(format!(
+    let out_file = std::fs::File::create(output).context(format!(
         "failed to create the output file:{}",
         output.to_string_lossy(),
     ))?;
-
-    let mut writer = BufWriter::new(out_file);
-    let result: Vec<u8>;
-    if root_list.len() > 1 {
-        let mut new_parent = Sha1Entity::new("new_folder".to_owned());
-        new_parent.dirs = root_list;
-        result = serde_json::to_vec(&new_parent)?;
+    let mut writer = std::io::BufWriter::new(out_file);
+    if preserve {
+        let (mut roots, files) = tree.into_roots();
+        if roots.is_empty() && files.is_empty() {
+            bail!("no valid line: {:?}", input);
+        }
+        if !files.is_empty() {
+            let mut root = Sha1Entity::new(String::new());
+            root.files = files;
+            root.dirs = roots;
+            serde_json::to_writer(&mut writer, &root)?;
+        } else if roots.len() == 1 {
+            serde_json::to_writer(&mut writer, &roots.remove(0))?;
+        } else {
+            serde_json::to_writer(&mut writer, &roots)?;
+        }
     } else {
-        result = serde_json::to_vec(&root_list[0])?;
+        let entity = tree.finish().context(format!("{:?}", input))?;
+        serde_json::to_writer(&mut writer, &entity)?;
     }
-    writer.write_all(&result).await?;
-    writer.flush().await?;
+    std::io::Write::flush(&mut writer)?;
 
     Ok(())
 }
 
-pub(crate) async fn path_to_sha1_entity(input: &Path) -> Result<Sha1Entity> {
-    check_input(input).await?;
+/// folder tree of the lines, lines without folder info sit in the synthetic root
+pub(crate) fn entity_from_lines(records: impl IntoIterator<Item = Sha1Line>) -> Result<Sha1Entity> {
+    let mut tree = TreeBuilder::keeping_loose_files();
+    records
+        .into_iter()
+        .for_each(|record| tree.push_line(record));
+    tree.finish()
+}
 
-    let mut json_file = open_without_bom(input).await?;
+struct TreeNode {
+    name: String,
+    files: Vec<FileRepr>,
+    /// children in the order they first show up
+    dirs: Vec<usize>,
+    /// children by name
+    index: HashMap<String, usize>,
+    line_number: Option<u64>,
+}
 
-    let mut json: Vec<u8> = Vec::new();
-    json_file.read_to_end(&mut json).await?;
-    json_file.flush().await?;
+impl TreeNode {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            files: Vec::new(),
+            dirs: Vec::new(),
+            index: HashMap::new(),
+            line_number: None,
+        }
+    }
+}
 
-    let sha1: Sha1Entity = serde_json::from_slice(&json)?;
+/// builds a folder tree one path at a time, folders are found through an index instead of a scan
+/// of their siblings, so big lists stay linear
+pub(crate) struct TreeBuilder {
+    /// every folder, the first one is the invisible root above the top level folders
+    nodes: Vec<TreeNode>,
+    /// lines without folders go above the top level folders instead of being left out
+    keep_loose: bool,
+}
 
-    Ok(sha1)
+impl Default for TreeBuilder {
+    fn default() -> Self {
+        Self {
+            nodes: vec![TreeNode::new(String::new())],
+            keep_loose: false,
+        }
+    }
 }
 
-pub(crate) async fn check_dup_n_err(path: &Path) -> Result<(usize, usize)> {
-    check_input(path).await?;
-    let file = open_without_bom(path).await?;
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines();
-    let mut invalid_lines: usize = 0;
-    let mut list: Vec<FileRepr> = Vec::new();
+impl TreeBuilder {
+    /// lines without folders are kept above the top level folders
+    pub(crate) fn keeping_loose_files() -> Self {
+        Self {
+            keep_loose: true,
+            ..Self::default()
+        }
+    }
 
-    while let Some(line) = lines
-        .next_line()
-        .await
-        .context("fail to read line, maybe not utf8?")?
-    {
-        if line.is_empty() || line.chars().all(|c| c.is_ascii_whitespace()) {
-            continue;
+    /// the folder at `path`, created along with its parents as needed
+    fn folder(&mut self, path: &[impl AsRef<str>]) -> usize {
+        let mut node = 0;
+        for name in path {
+            let name = name.as_ref();
+            node = match self.nodes[node].index.get(name) {
+                Some(child) => *child,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes.push(TreeNode::new(name.to_owned()));
+                    let parent = &mut self.nodes[node];
+                    parent.index.insert(name.to_owned(), child);
+                    parent.dirs.push(child);
+                    child
+                }
+            };
         }
+        node
+    }
 
-        let repr = match FileRepr::from_str(&line) {
-            Ok(file) => file,
-            Err(_) => {
-                log::warn!("invalid line during check dup_n_err : {}", line);
-                invalid_lines += 1;
-                continue;
-            }
-        };
+    /// a file at `path`, an empty path puts it above the top level folders
+    pub(crate) fn push_file(&mut self, path: &[impl AsRef<str>], file: FileRepr) {
+        let folder = self.folder(path);
+        self.nodes[folder].files.push(file);
+    }
+
+    /// a folder that may stay empty, `line_number` of the line that listed it
+    pub(crate) fn push_folder(&mut self, path: &[impl AsRef<str>], line_number: Option<u64>) {
+        let folder = self.folder(path);
+        let node = &mut self.nodes[folder];
+        node.line_number = node.line_number.or(line_number);
+    }
+
+    /// lines without folder info are left out unless loose files are kept
+    pub(crate) fn push_line(&mut self, record: Sha1Line) {
+        if record.folders.is_empty() && !self.keep_loose {
+            log::warn!("line without folder: {}", record.line_number);
+            return;
+        }
+        self.push_file(&record.folders, record.file);
+    }
+
+    fn take(&mut self, node: usize) -> Sha1Entity {
+        let TreeNode {
+            name,
+            files,
+            dirs,
+            line_number,
+            ..
+        } = std::mem::replace(&mut self.nodes[node], TreeNode::new(String::new()));
+        let mut entity = Sha1Entity::new(name);
+        entity.files = files;
+        entity.line_number = line_number;
+        entity.dirs = dirs.into_iter().map(|dir| self.take(dir)).collect();
+        entity
+    }
 
-        list.push(repr);
+    /// the top level folders and the files above them
+    pub(crate) fn into_roots(mut self) -> (Vec<Sha1Entity>, Vec<FileRepr>) {
+        let root = self.take(0);
+        (root.dirs, root.files)
+    }
+
+    /// one entity, a synthetic parent is added above several top level folders
+    pub(crate) fn finish(self) -> Result<Sha1Entity> {
+        let (roots, files) = self.into_roots();
+        if roots.is_empty() && files.is_empty() {
+            bail!("no line with folder info");
+        }
+        wrap_roots(roots, files)
+    }
+}
+
+/// the list and the json shape it was written in
+pub(crate) async fn path_to_sha1_entity(input: &Path) -> Result<(Sha1Entity, JsonFormat)> {
+    check_input(input).await?;
+
+    let json = read_utf8(input).await?;
+    parse_sha1_json(json.as_bytes())
+}
+
+/// number of duplicated links and every line that failed to parse
+pub(crate) async fn check_dup_n_err(path: &Path) -> Result<(usize, Vec<RejectedLine>)> {
+    let mut reader = Sha1LineReader::open(path).await?;
+    let mut rejected_lines = Vec::new();
+    let mut list: Vec<FileRepr> = Vec::new();
+
+    while let Some(parsed) = reader.next_line().await? {
+        match parsed {
+            Ok(record) => list.push(record.file),
+            Err(rejected) => {
+                log::warn!("invalid line during check dup_n_err : {}", rejected);
+                rejected_lines.push(rejected);
+            }
+        }
     }
 
     let origin = list.len();
@@ -205,40 +359,55 @@ pub(crate) async fn check_dup_n_err(path: &Path) -> Result<(usize, usize)> {
     let list = dedup_filerepr_vec(list);
     let after = list.len();
 
-    Ok((origin - after, invalid_lines))
+    Ok((origin - after, rejected_lines))
+}
+
+/// writes one line per rejected line with its line number and reason, returns how many
+pub(crate) async fn line_errors_report(input: &Path, output: &Path) -> Result<usize> {
+    check_input_output(input, output).await?;
+
+    let mut reader = Sha1LineReader::open(input).await?;
+    let mut content = String::new();
+    let mut count = 0;
+    while let Some(parsed) = reader.next_line().await? {
+        if let Err(rejected) = parsed {
+            count += 1;
+            content.push_str(&format!("{}\n", rejected));
+        }
+    }
+
+    if count != 0 {
+        write_all_to_file(output, content.as_bytes()).await?;
+    }
+    Ok(count)
 }
 
 pub(crate) async fn dedup_filerepr_file(input: &Path, output: &Path) -> Result<()> {
     check_input_output(input, output).await?;
 
-    let file = open_without_bom(input).await?;
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines();
+    let mut reader = Sha1LineReader::open(input).await?;
     let mut set = HashSet::new();
 
     let output = TokioFile::create(output).await?;
     let mut writer = BufWriter::new(output);
 
-    while let Some(line) = lines
-        .next_line()
-        .await
-        .context("fail to read line, maybe not utf8?")?
-    {
-        if line.is_empty() || line.chars().all(|c| c.is_ascii_whitespace()) {
-            continue;
-        }
-
-        let repr = match FileRepr::from_str(&line) {
-            Ok(file) => file,
-            Err(_) => {
-                log::warn!("invalid line during dedup file {:?} info: {}", input, line,);
+    while let Some(parsed) = reader.next_line().await? {
+        let record = match parsed {
+            Ok(record) => record,
+            Err(rejected) => {
+                log::warn!(
+                    "invalid line during dedup file {:?} info: {}",
+                    input,
+                    rejected
+                );
                 continue;
             }
         };
 
-        if !set.contains(&repr.unique_key()) {
-            set.insert(repr.unique_key());
-            writer.write_all(format!("{}\n", line).as_bytes()).await?;
+        // kept lines are written as they were
+        if set.insert(record.file.unique_key()) {
+            writer.write_all(reader.line().as_bytes()).await?;
+            writer.write_all(b"\n").await?;
         }
     }
 
@@ -249,199 +418,247 @@ pub(crate) async fn dedup_filerepr_file(input: &Path, output: &Path) -> Result<(
 
 pub(crate) fn dedup_filerepr_vec(mut list: Vec<FileRepr>) -> Vec<FileRepr> {
     let mut set = HashSet::new();
-    list.retain(|item| {
-        if set.contains(&item.unique_key()) {
-            false
-        } else {
-            set.insert(item.unique_key());
-            true
-        }
-    });
+    list.retain(|item| set.insert(item.unique_key()));
 
     list
 }
 
-pub(crate) async fn decrypt_line_file(input: &Path, output: &Path) -> Result<()> {
+/// lines that can't be decrypted go to `failed_output` instead
+pub(crate) async fn decrypt_line_file(
+    input: &Path,
+    output: &Path,
+    failed_output: &Path,
+) -> Result<DecryptReport> {
     check_input_output(input, output).await?;
 
-    let file = open_without_bom(input).await?;
-    let reader = tokio::io::BufReader::new(file);
-    let mut lines = reader.lines();
+    let mut reader = Sha1LineReader::open(input).await?;
+    let mut decoder = PreidDecoder::default();
+    let mut report = DecryptReport::default();
     let mut content = String::new();
 
-    let mut i: i64 = 0;
-    while let Some(line) = lines
-        .next_line()
-        .await
-        .context("fail to read line, maybe not utf8?")?
-    {
-        i += 1;
-        if line.is_empty() || line.chars().all(|c| c.is_ascii_whitespace()) {
-            continue;
-        }
-        // line.splitn(n, pat)
-        let part: Vec<&str> = line.splitn(8, '|').collect();
-        if part.len() != 8 {
-            bail!("wrong 115sha1 format during decryption {}", i);
-        }
-
-        let filename = part[0];
-        let size = part[1];
-        let sha1 = part[2];
-        let encrypted_preid = part[3];
-        let path_str = part[6];
-
-        if encrypted_preid == "0" || encrypted_preid == "error" {
+    while let Some(parsed) = reader.next_line().await? {
+        let record = match parsed {
+            Ok(record) => record,
             // export err type
+            Err(RejectedLine {
+                reason: LineError::MissingBlockHash,
+                ..
+            }) => continue,
+            Err(rejected) => {
+                report.failed.push(rejected.to_string());
+                continue;
+            }
+        };
+        let fail = |reason: &str| {
+            format!(
+                "第 {} 行: {}\t{}",
+                record.line_number,
+                reason,
+                record.to_line()
+            )
+        };
+        // name|size|sha1|preid|_|_|path_str|_
+        let fields = record.trailing_fields();
+        if fields.len() < 4 {
+            report.failed.push(fail("缺少路径信息"));
             continue;
         }
-        let preid = if encrypted_preid.chars().all(|c| c.is_ascii_hexdigit())
-            && encrypted_preid.len() == 40
-        {
-            encrypted_preid.to_owned()
-        } else {
-            preid_decrypt(encrypted_preid)?
+        let path_str = match format_path_str(fields[2]) {
+            Ok(path_str) => path_str,
+            Err(_) => {
+                report.failed.push(fail("路径信息格式不正确"));
+                continue;
+            }
         };
-        let path_str = format_path_str(path_str)?;
-        let filename = if filename.starts_with("115://") {
-            filename.to_owned()
-        } else {
-            format!("115://{}", filename)
+        let block = match record.file.sha1_block() {
+            BlockHash::Encrypted(preid) => match decoder.decrypt(preid) {
+                Ok(block) => block,
+                Err(e) => {
+                    log::warn!("failed to decrypt line {}: {}", record.line_number, e);
+                    report.failed.push(fail("无法解密"));
+                    continue;
+                }
+            },
+            BlockHash::Plain(block) => *block,
         };
+        let file = record.file;
         content.push_str(&format!(
-            "{}|{}|{}|{}|{}\n",
-            filename, size, sha1, &preid, &path_str
+            "115://{}|{}|{}|{}|{}\n",
+            file.name, file.size, file.sha1, block, &path_str
         ));
+        report.decrypted += 1;
     }
+    report.form = decoder.detected();
 
     if !content.is_empty() {
         write_all_to_file(output, content.as_bytes()).await?;
     }
+    if !report.failed.is_empty() {
+        write_all_to_file(failed_output, report.details().as_bytes()).await?;
+    }
 
-    Ok(())
+    Ok(report)
 }
 
-pub(crate) fn line_summary_mem(content: &str) -> Result<Summary> {
-    let mut all_size: Vec<u64> = Vec::new();
-    let mut num_lines: u64 = 0;
-    let mut has_folder = true;
-    let mut encrypted = false;
+/// plain block sha1s turned into short preids, returns how many were encrypted
+pub(crate) async fn encrypt_line_file(input: &Path, output: &Path) -> Result<usize> {
+    check_input_output(input, output).await?;
 
-    for line in content.lines() {
-        if line.is_empty() || line.chars().all(|c| c.is_ascii_whitespace()) {
-            continue;
-        }
-        num_lines += 1;
-        let mut parts = line.split('|');
-        // log::info!("{}", line);
-        let size: u64 = parts
-            .nth(1)
-            .ok_or_else(|| anyhow!("wrong format"))?
-            .parse()?;
-        all_size.push(size);
-        let preid = parts.nth(1).ok_or_else(|| anyhow!("wrong format"))?;
-        if !(preid.chars().all(|c| c.is_ascii_hexdigit()) && preid.len() == 40) {
-            encrypted = true;
-        }
-        if parts.next().is_none() {
-            has_folder = false;
+    let mut reader = Sha1LineReader::open(input).await?;
+    let mut content = String::new();
+    let mut encrypted = 0;
+
+    while let Some(parsed) = reader.next_line().await? {
+        let mut record = match parsed {
+            Ok(record) => record,
+            Err(rejected) => {
+                log::warn!("invalid line during encryption: {}", rejected);
+                continue;
+            }
+        };
+        if let BlockHash::Plain(block) = record.file.sha1_block {
+            record.file.sha1_block = BlockHash::Encrypted(CODEC.encrypt(&block, PreidForm::Short)?);
+            encrypted += 1;
         }
-    }
-    if num_lines == 0 {
-        bail!("empty lines!");
+        content.push_str(&record.to_line());
+        content.push('\n');
     }
 
-    all_size.sort_unstable();
-    let max: u64 = all_size[all_size.len() - 1];
-    let min = all_size[0];
-    let total_size = all_size.iter().sum();
+    if encrypted != 0 {
+        write_all_to_file(output, content.as_bytes()).await?;
+    }
+    Ok(encrypted)
+}
 
-    let mid: f64 = if all_size.len() % 2 == 1 {
-        all_size[all_size.len() / 2] as f64
-    } else {
-        let left = all_size[all_size.len() / 2 - 1];
-        let right = all_size[all_size.len() / 2];
-        (left + right) as f64 / 2.0
-    };
+pub(crate) fn line_summary_mem(content: &str) -> Result<Summary> {
+    let mut builder = SummaryBuilder::default();
+    parse_lines(content)
+        .flatten()
+        .for_each(|record| builder.push(&record));
+    builder.finish().context("empty lines!")
+}
 
-    Ok(Summary {
-        total_size,
-        max,
-        min,
-        mid,
-        total_files: num_lines,
-        has_folder,
-        encrypted,
-    })
+pub(crate) fn records_summary<'a>(
+    records: impl IntoIterator<Item = &'a Sha1Line>,
+) -> Result<Summary> {
+    let mut builder = SummaryBuilder::default();
+    records.into_iter().for_each(|record| builder.push(record));
+    builder.finish().context("empty lines!")
 }
 
 pub(crate) async fn line_summary(path: &Path) -> Result<Summary> {
-    check_input(path).await?;
-
-    let mut all_size: Vec<u64> = Vec::new();
-    let mut num_lines: u64 = 0;
-    let mut has_folder = true;
+    let mut reader = Sha1LineReader::open(path).await?;
+    let mut builder = SummaryBuilder::default();
+    while let Some(parsed) = reader.next_line().await? {
+        if let Ok(record) = parsed {
+            builder.push(&record);
+        }
+    }
+    builder.finish().context(format!(
+        "failed to read line of file:{}",
+        path.to_string_lossy()
+    ))
+}
 
-    let mut encrypted = false;
+/// how many of the largest files a detailed summary names
+const SUMMARY_TOP_FILES: usize = 5;
+
+/// collects what a `Summary` needs while the lines stream by
+#[derive(Default)]
+struct SummaryBuilder {
+    all_size: Vec<u64>,
+    kinds: BTreeMap<FileKind, (u64, u64)>,
+    /// every folder path, parents included
+    folders: HashSet<String>,
+    max_depth: usize,
+    /// largest first
+    largest: Vec<(u64, String)>,
+    extensions: HashSet<String>,
+    missing_folder: bool,
+    encrypted: bool,
+}
 
-    let file = open_without_bom(path).await?;
-    let reader = tokio::io::BufReader::new(file);
-    let mut lines = reader.lines();
-    while let Some(line) = lines
-        .next_line()
-        .await
-        .context("fail to read line, maybe not utf8?")?
-    {
-        if line.is_empty() || line.chars().all(|c| c.is_ascii_whitespace()) {
-            continue;
+impl SummaryBuilder {
+    fn push(&mut self, record: &Sha1Line) {
+        let size = record.file.size;
+        self.all_size.push(size);
+        let kind = self.kinds.entry(record.file.kind()).or_default();
+        kind.0 += 1;
+        kind.1 += size;
+        self.encrypted |= record.encrypted;
+        self.missing_folder |= record.folders.is_empty();
+
+        self.max_depth = self.max_depth.max(record.folders.len());
+        // once a path is known its parents are too
+        if !self.folders.contains(&record.folders.join("/")) {
+            for depth in 1..=record.folders.len() {
+                self.folders.insert(record.folders[..depth].join("/"));
+            }
         }
-        // log::info!("{}", line);
-        num_lines += 1;
-        let mut parts = line.split('|');
-        let size: u64 = parts
-            .nth(1)
-            .ok_or_else(|| anyhow!("wrong format"))?
-            .parse()?;
-        all_size.push(size);
-        let preid = parts.nth(1).ok_or_else(|| anyhow!("wrong format"))?;
-        if !(preid.chars().all(|c| c.is_ascii_hexdigit()) && preid.len() == 40) {
-            encrypted = true;
+
+        let ext = record.file.extension();
+        if !ext.is_empty() {
+            self.extensions.insert(ext);
         }
 
-        if parts.next().is_none() {
-            has_folder = false;
+        if self.largest.len() < SUMMARY_TOP_FILES
+            || self
+                .largest
+                .last()
+                .map(|(min, _)| size > *min)
+                .unwrap_or(true)
+        {
+            let at = self.largest.partition_point(|(s, _)| *s >= size);
+            self.largest
+                .insert(at, (size, record.file.name().to_owned()));
+            self.largest.truncate(SUMMARY_TOP_FILES);
         }
     }
 
-    if num_lines == 0 {
-        bail!("failed to read line of file:{}", path.to_string_lossy());
-    }
-    all_size.sort_unstable();
-    let max: u64 = all_size[all_size.len() - 1];
-    let min = all_size[0];
-    let total_size = all_size.iter().sum();
+    fn finish(self) -> Option<Summary> {
+        let mut all_size = self.all_size;
+        if all_size.is_empty() {
+            return None;
+        }
+        all_size.sort_unstable();
+        let max: u64 = all_size[all_size.len() - 1];
+        let min = all_size[0];
+        let total_size = all_size.iter().sum();
 
-    let mid: f64 = if all_size.len() % 2 == 1 {
-        all_size[all_size.len() / 2] as f64
-    } else {
-        let left = all_size[all_size.len() / 2 - 1];
-        let right = all_size[all_size.len() / 2];
-        (left as f64 + right as f64) / 2.0
-    };
+        let mid: f64 = if all_size.len() % 2 == 1 {
+            all_size[all_size.len() / 2] as f64
+        } else {
+            let left = all_size[all_size.len() / 2 - 1];
+            let right = all_size[all_size.len() / 2];
+            (left as f64 + right as f64) / 2.0
+        };
 
-    Ok(Summary {
-        total_size,
-        max,
-        min,
-        mid,
-        total_files: num_lines,
-        has_folder,
-        encrypted,
-    })
+        // nearest rank
+        let percentile = |p: usize| all_size[(all_size.len() * p).div_ceil(100) - 1];
+
+        Some(Summary {
+            total_size,
+            max,
+            min,
+            mid,
+            mean: total_size as f64 / all_size.len() as f64,
+            p90: percentile(90),
+            p99: percentile(99),
+            total_files: all_size.len() as u64,
+            zero_byte_files: all_size.iter().take_while(|size| **size == 0).count() as u64,
+            folders: self.folders.len() as u64,
+            max_depth: self.max_depth,
+            largest: self.largest.into_iter().map(|(s, n)| (n, s)).collect(),
+            extensions: self.extensions.len() as u64,
+            kinds: self.kinds,
+            has_folder: !self.missing_folder,
+            encrypted: self.encrypted,
+        })
+    }
 }
 
 pub(crate) fn json_summary(entity: &Sha1Entity) -> Result<Summary> {
-    let lines = json2line_mem(entity)?;
+    let lines = entity_lines(entity, false);
     let mut res = line_summary_mem(&lines)?;
     res.has_folder = true;
     Ok(res)
@@ -453,7 +670,21 @@ pub(crate) struct Summary {
     pub(crate) max: u64,
     pub(crate) min: u64,
     pub(crate) mid: f64,
+    pub(crate) mean: f64,
+    pub(crate) p90: u64,
+    pub(crate) p99: u64,
     pub(crate) total_files: u64,
+    pub(crate) zero_byte_files: u64,
+    /// distinct folder paths, parents included
+    pub(crate) folders: u64,
+    /// folder levels of the deepest file
+    pub(crate) max_depth: usize,
+    /// name and size, largest first
+    pub(crate) largest: Vec<(String, u64)>,
+    /// distinct extensions
+    pub(crate) extensions: u64,
+    /// file count and size of every kind present
+    pub(crate) kinds: BTreeMap<FileKind, (u64, u64)>,
     pub(crate) has_folder: bool,
     pub(crate) encrypted: bool,
 }
@@ -468,29 +699,177 @@ pub(crate) fn to_iec(num: impl Into<u128>) -> String {
     res
 }
 
+/// `1024`, `500MB`, `1.5T`, `2TiB`, units are powers of 1024 like `to_iec`
+pub(crate) fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num: f64 = num.parse().ok()?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let unit = unit.trim_end_matches('B').trim_end_matches('I');
+    let power = match unit {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        "P" => 5,
+        _ => return None,
+    };
+    Some((num * 1024f64.powi(power)) as u64)
+}
+
+impl Summary {
+    fn kinds_line(&self) -> String {
+        let mut kinds: Vec<_> = self.kinds.iter().collect();
+        kinds.sort_by_key(|(_, (_, size))| std::cmp::Reverse(*size));
+        kinds
+            .into_iter()
+            .map(|(kind, (files, size))| format!("{} {} 个 {}", kind, files, to_iec(*size)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// everything we know, for private chats
+    pub(crate) fn detailed(&self) -> String {
+        let mut res = format!(
+            "共 {} 个文件, {}\n最小 {}, 最大 {}, 中位 {}\n平均 {}, P90 {}, P99 {}\n空文件 {} 个, 扩展名 {} 种",
+            self.total_files,
+            to_iec(self.total_size),
+            to_iec(self.min),
+            to_iec(self.max),
+            to_iec(self.mid as u128),
+            to_iec(self.mean as u128),
+            to_iec(self.p90),
+            to_iec(self.p99),
+            self.zero_byte_files,
+            self.extensions,
+        );
+        if self.has_folder {
+            res.push_str(&format!(
+                "\n目录 {} 个, 最深 {} 层",
+                self.folders, self.max_depth
+            ));
+        }
+        if !self.kinds.is_empty() {
+            res.push_str(&format!("\n{}", self.kinds_line()));
+        }
+        if !self.largest.is_empty() {
+            res.push_str("\n\n最大的文件:");
+            for (name, size) in &self.largest {
+                let mut name = name.to_owned();
+                if name.chars().count() > 60 {
+                    name = name.chars().take(60).collect::<String>() + "...";
+                }
+                res.push_str(&format!("\n{:>7} => {}", to_iec(*size), name));
+            }
+        }
+        res
+    }
+
+    /// short in groups, detailed in private chats
+    pub(crate) fn for_chat(&self, private: bool) -> String {
+        if private {
+            self.detailed()
+        } else {
+            self.to_string()
+        }
+    }
+}
+
 impl std::fmt::Display for Summary {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "共 {} 个文件, {}\n最小 {}, 最大 {}, 中位 {}",
+            "共 {} 个文件, {}\n最小 {}, 最大 {}, 中位 {}, 平均 {}",
             self.total_files,
             to_iec(self.total_size),
             to_iec(self.min),
             to_iec(self.max),
             to_iec(self.mid as u128),
-        )
+            to_iec(self.mean as u128),
+        )?;
+        if !self.kinds.is_empty() {
+            write!(f, "\n{}", self.kinds_line())?;
+        }
+        Ok(())
     }
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Deserialize)]
+#[serde(from = "EntityFields")]
 pub(crate) struct Sha1Entity {
+    pub(crate) dir_name: String,
+    pub(crate) files: Vec<FileRepr>,
+    pub(crate) dirs: Vec<Self>,
+    id: Option<u64>,
+    /// where the empty folder line of this folder was in the list it came from
+    pub(crate) line_number: Option<u64>,
+    /// made up to hold several roots, its name is not a folder of the list
+    pub(crate) synthetic: bool,
+}
+
+/// `Sha1Entity` as it is written in json, line numbers of the files sit next to them
+#[derive(Serialize, Deserialize)]
+struct EntityFields<F = FileRepr, D = Sha1Entity> {
     #[serde(deserialize_with = "from_dirty_string")]
     dir_name: String,
-    files: Vec<FileRepr>,
-    dirs: Vec<Self>,
-    #[serde(skip_serializing)]
+    files: Vec<F>,
+    dirs: Vec<D>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    line_number: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    line_numbers: Vec<Option<u64>>,
+}
+
+impl From<EntityFields> for Sha1Entity {
+    fn from(fields: EntityFields) -> Self {
+        let mut files = fields.files;
+        for (file, line_number) in files.iter_mut().zip(fields.line_numbers) {
+            file.line_number = line_number;
+        }
+        Self {
+            dir_name: fields.dir_name,
+            files,
+            dirs: fields.dirs,
+            id: fields.id,
+            line_number: fields.line_number,
+            synthetic: false,
+        }
+    }
+}
+
+impl Serialize for Sha1Entity {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let line_numbers = if self.files.iter().any(|f| f.line_number.is_some()) {
+            self.files.iter().map(|f| f.line_number).collect()
+        } else {
+            Vec::new()
+        };
+        // a synthetic root holding files is written nameless, which reads back as no folder
+        let dir_name = if self.synthetic && !self.files.is_empty() {
+            String::new()
+        } else {
+            self.dir_name.to_owned()
+        };
+        EntityFields {
+            dir_name,
+            files: self.files.iter().collect(),
+            dirs: self.dirs.iter().collect(),
+            id: self.id,
+            line_number: self.line_number,
+            line_numbers,
+        }
+        .serialize(serializer)
+    }
 }
 
 fn from_dirty_string<'de, D>(deserializer: D) -> Result<String, D::Error>
@@ -509,6 +888,8 @@ impl Sha1Entity {
             files: Vec::new(),
             dirs: Vec::new(),
             id: None,
+            line_number: None,
+            synthetic: false,
         }
     }
 }
@@ -520,75 +901,210 @@ impl FromStr for Sha1Entity {
         if !path.exists() {
             return Err(anyhow!("File not exist."));
         }
-        let file = std::fs::File::open(s)?;
-        let mut reader = std::io::BufReader::new(file);
-        if has_bom(path)? {
-            reader.seek_relative(3)?;
+        let json = read_utf8_sync(path)?;
+        Ok(parse_sha1_json(json.as_bytes())?.0)
+    }
+}
+
+/// a sha1 kept as its 20 bytes, written back in the case it was read in, hashes with mixed case
+/// come out uppercase
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Sha1Hash {
+    bytes: [u8; 20],
+    lowercase: bool,
+}
+
+impl Sha1Hash {
+    pub(crate) const fn from_bytes(bytes: [u8; 20]) -> Self {
+        Self {
+            bytes,
+            lowercase: false,
         }
-        Ok(serde_json::from_reader::<_, Sha1Entity>(reader)?)
+    }
+
+    /// the digits in uppercase, whatever case the hash was read in
+    pub(crate) fn to_uppercase(self) -> String {
+        HEXUPPER.encode(&self.bytes)
+    }
+}
+
+/// the same hash in either case
+impl PartialEq for Sha1Hash {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes == other.bytes
     }
 }
 
+impl Eq for Sha1Hash {}
+
+impl std::hash::Hash for Sha1Hash {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.bytes.hash(state);
+    }
+}
+
+/// 40 hex digits in either case
+impl FromStr for Sha1Hash {
+    type Err = WrongSha1LinkFormat;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut bytes = [0; 20];
+        if s.len() != 40 {
+            return Err(WrongSha1LinkFormat);
+        }
+        HEXUPPER_PERMISSIVE
+            .decode_mut(s.as_bytes(), &mut bytes)
+            .map_err(|_| WrongSha1LinkFormat)?;
+        let lowercase =
+            s.bytes().any(|b| b.is_ascii_lowercase()) && !s.bytes().any(|b| b.is_ascii_uppercase());
+        Ok(Self { bytes, lowercase })
+    }
+}
+
+impl fmt::Display for Sha1Hash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.lowercase {
+            write!(f, "{}", HEXLOWER.encode(&self.bytes))
+        } else {
+            write!(f, "{}", HEXUPPER.encode(&self.bytes))
+        }
+    }
+}
+
+/// the block sha1, or whatever else was in its place, normally an encrypted preid
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum BlockHash {
+    Plain(Sha1Hash),
+    Encrypted(String),
+}
+
+impl From<&str> for BlockHash {
+    fn from(s: &str) -> Self {
+        match s.parse() {
+            Ok(hash) => BlockHash::Plain(hash),
+            Err(_) => BlockHash::Encrypted(s.to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for BlockHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockHash::Plain(hash) => write!(f, "{}", hash),
+            BlockHash::Encrypted(preid) => write!(f, "{}", preid),
+        }
+    }
+}
+
+/// files are the same when size, sha1 and block sha1 are
+pub(crate) type FileKey = (u64, Sha1Hash, BlockHash);
+
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct FileRepr {
     // #[serde(deserialize_with = "from_dirty_string")]
     name: String,
     size: u64,
-    sha1: String,
-    sha1_block: String,
+    sha1: Sha1Hash,
+    sha1_block: BlockHash,
+    /// fields after the block sha1 no tool here knows about, kept so conversions give them back
+    extra: Vec<String>,
     id: Option<u64>,
+    /// where the file was in the list it came from, json keeps it so the lines can be written back
+    /// in their order
+    line_number: Option<u64>,
 }
 
 impl FromStr for FileRepr {
     type Err = WrongSha1LinkFormat;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut parts = s.split('|');
-        let mut name = parts.next().ok_or(WrongSha1LinkFormat)?.to_string();
-        if name.starts_with("115://") {
-            name = name.strip_prefix("115://").unwrap().to_string();
-        }
-        let size = parts
-            .next()
-            .ok_or(WrongSha1LinkFormat)?
-            .parse()
-            .map_err(|_| WrongSha1LinkFormat)?;
-
-        let sha1 = parts.next().ok_or(WrongSha1LinkFormat)?.to_string();
-        let sha1_block = parts.next().ok_or(WrongSha1LinkFormat)?.to_string();
-
-        if sha1.len() != 40 || sha1_block.len() != 40 {
-            return Err(WrongSha1LinkFormat);
-        }
-        if !(sha1.chars().all(|c| c.is_digit(16)) && sha1_block.chars().all(|c| c.is_digit(16))) {
-            return Err(WrongSha1LinkFormat);
+        match parse_line(s, 0) {
+            Ok(Some(record)) if !record.encrypted => Ok(record.file),
+            _ => Err(WrongSha1LinkFormat),
         }
+    }
+}
 
-        Ok(FileRepr {
+impl FileRepr {
+    pub(crate) fn new(name: String, size: u64, sha1: Sha1Hash, sha1_block: BlockHash) -> Self {
+        Self {
             name,
             size,
             sha1,
             sha1_block,
+            extra: Vec::new(),
             id: None,
-        })
+            line_number: None,
+        }
     }
-}
 
-impl FileRepr {
-    fn to_sha1_link(&self) -> String {
-        "115://".to_owned()
-            + &[
-                self.name.to_owned(),
-                self.size.to_string(),
-                self.sha1.to_owned(),
-                self.sha1_block.to_owned(),
-            ]
-            .join("|")
+    pub(crate) fn with_extra(mut self, extra: Vec<String>) -> Self {
+        self.extra = extra;
+        self
+    }
+
+    pub(crate) fn extra(&self) -> &[String] {
+        &self.extra
     }
 
-    fn unique_key(&self) -> String {
-        format!("{}{}{}", self.size, self.sha1, self.sha1_block)
+    pub(crate) fn line_number(&self) -> Option<u64> {
+        self.line_number
+    }
+
+    pub(crate) fn set_line_number(&mut self, line_number: Option<u64>) {
+        self.line_number = line_number;
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// lowercase, empty when there is none
+    pub(crate) fn extension(&self) -> String {
+        match self.name.rsplit_once('.') {
+            Some((stem, ext)) if !stem.is_empty() => ext.to_lowercase(),
+            _ => String::new(),
+        }
+    }
+
+    pub(crate) fn kind(&self) -> FileKind {
+        FileKind::from_name(&self.name)
+    }
+
+    pub(crate) fn sha1(&self) -> &Sha1Hash {
+        &self.sha1
+    }
+
+    pub(crate) fn sha1_block(&self) -> &BlockHash {
+        &self.sha1_block
+    }
+
+    pub(crate) fn to_sha1_link(&self) -> String {
+        format!("115://{}", self)
+    }
+
+    pub(crate) fn unique_key(&self) -> FileKey {
+        (self.size, self.sha1, self.sha1_block.clone())
+    }
+}
+
+/// `name|size|sha1|block_sha1`
+impl fmt::Display for FileRepr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}|{}|{}|{}",
+            self.name, self.size, self.sha1, self.sha1_block
+        )
     }
 }
 
@@ -597,15 +1113,10 @@ impl Serialize for FileRepr {
     where
         S: serde::Serializer,
     {
-        let mut out = [
-            self.name.to_owned(),
-            self.size.to_string(),
-            self.sha1.to_owned(),
-            self.sha1_block.to_owned(),
-        ]
-        .join("|");
-        if out.starts_with("115://") {
-            out = out.strip_prefix("115://").unwrap().to_owned();
+        let mut out = self.to_string();
+        for field in &self.extra {
+            out.push('|');
+            out.push_str(field);
         }
         serializer.serialize_str(&out)
     }
@@ -624,9 +1135,16 @@ impl std::error::Error for WrongSha1LinkFormat {}
 
 use serde::de::Error;
 
-use crate::decryption::{format_path_str, preid_decrypt};
-use crate::global::ROOT_FOLDER;
-use crate::io::{check_input, check_input_output, has_bom, open_without_bom};
+use crate::decryption::{format_path_str, DecryptReport, PreidDecoder, PreidForm, CODEC};
+use crate::file_kind::FileKind;
+use crate::io::{check_input, check_input_output, open_utf8, read_utf8, read_utf8_sync};
+use crate::line_parser::{
+    parse_line, parse_lines, LineError, LineItem, RejectedLine, Sha1Line, Sha1LineReader,
+    FOLDER_LINE_PREFIX,
+};
+use crate::providers::fetch_torrent;
+use crate::sha1_json::{parse_sha1_json, top_level, wrap_roots, JsonFormat};
+use crate::text_encoding;
 impl<'de> Deserialize<'de> for FileRepr {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -659,19 +1177,23 @@ impl<'de> Deserialize<'de> for FileRepr {
             .next()
             .ok_or(WrongSha1LinkFormat)
             .map_err(D::Error::custom)?
-            .to_owned();
+            .parse()
+            .map_err(D::Error::custom)?;
         let sha1_block = sp
             .next()
             .ok_or(WrongSha1LinkFormat)
             .map_err(D::Error::custom)?
-            .to_owned();
+            .into();
+        let extra = sp.map(str::to_owned).collect();
 
         Ok(Self {
             name,
             size,
             sha1,
             sha1_block,
+            extra,
             id: None,
+            line_number: None,
         })
     }
 }
@@ -686,114 +1208,6 @@ impl std::fmt::Display for Parse115SHA1Error {
     }
 }
 
-fn get_dir_or_create<'q>(name: &'_ str, queue: &'q mut Vec<Sha1Entity>) -> &'q mut Sha1Entity {
-    let index = queue
-        .iter()
-        .enumerate()
-        .find(|(_, p)| p.dir_name == name)
-        .map(|(i, _)| i)
-        .unwrap_or_else(|| {
-            queue.push(Sha1Entity::new(name.to_owned()));
-            queue.len() - 1
-        });
-
-    unsafe { queue.get_unchecked_mut(index) }
-}
-
-fn write_line_mem(res: &mut String, entity: &Sha1Entity, suffix: String) {
-    let suffix = suffix + "|" + &entity.dir_name;
-    for file in &entity.files {
-        let link = file.to_sha1_link();
-        let line = link + &suffix;
-        let line = line + "\n";
-        res.push_str(&line);
-    }
-    for dir in &entity.dirs {
-        write_line_mem(res, dir, suffix.to_owned());
-    }
-}
-
-fn write_line(writer: &mut std::io::BufWriter<std::fs::File>, entity: &Sha1Entity, suffix: String) {
-    use std::io::Write;
-    let suffix = suffix + "|" + &entity.dir_name;
-    for file in &entity.files {
-        let link = file.to_sha1_link();
-        let line = link + &suffix;
-        writeln!(writer, "{}", line).unwrap();
-    }
-    for dir in &entity.dirs {
-        write_line(writer, dir, suffix.to_owned());
-    }
-}
-
-#[allow(dead_code)]
-#[derive(Debug, Deserialize)]
-struct Node(String, i64);
-
-#[allow(dead_code)]
-#[derive(Debug, Deserialize, Serialize)]
-struct File {
-    path: Vec<String>,
-    length: i64,
-    #[serde(default)]
-    md5sum: Option<String>,
-}
-
-#[allow(dead_code)]
-#[derive(Debug, Deserialize, Serialize)]
-pub(crate) struct Info {
-    name: String,
-    pieces: ByteBuf,
-    #[serde(rename = "piece length")]
-    piece_length: i64,
-    #[serde(default)]
-    md5sum: Option<String>,
-    #[serde(default)]
-    length: Option<i64>,
-    #[serde(default)]
-    files: Option<Vec<File>>,
-    #[serde(default)]
-    private: Option<u8>,
-    #[serde(default)]
-    path: Option<Vec<String>>,
-    #[serde(default)]
-    #[serde(rename = "root hash")]
-    root_hash: Option<String>,
-}
-
-#[allow(dead_code)]
-#[derive(Debug, Deserialize, Serialize)]
-pub(crate) struct Torrent {
-    pub(crate) info: Info,
-    #[serde(default, skip)]
-    announce: Option<String>,
-    #[serde(default, skip)]
-    nodes: Option<Vec<Node>>,
-    #[serde(default, skip)]
-    encoding: Option<String>,
-    #[serde(default, skip)]
-    httpseeds: Option<Vec<String>>,
-    #[serde(default, skip)]
-    #[serde(rename = "announce-list")]
-    announce_list: Option<Vec<Vec<String>>>,
-    #[serde(default, skip)]
-    #[serde(rename = "creation date")]
-    creation_date: Option<i64>,
-    #[serde(skip)]
-    #[serde(rename = "comment")]
-    comment: Option<String>,
-    #[serde(default, skip)]
-    #[serde(rename = "created by")]
-    created_by: Option<String>,
-}
-
-#[allow(dead_code)]
-fn sha1(bytes: &[u8]) -> String {
-    let mut hasher = Sha1::new();
-    hasher.input(bytes);
-    hasher.result_str()
-}
-
 #[allow(dead_code)]
 fn md5(bytes: &[u8]) -> String {
     let mut hasher = Md5::new();
@@ -801,144 +1215,33 @@ fn md5(bytes: &[u8]) -> String {
     hasher.result_str()
 }
 
-#[allow(dead_code)]
-fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
-    haystack
-        .windows(needle.len())
-        .position(|window| window == needle)
-}
-
-pub(crate) async fn get_torrent_magnet_async(path: &Path) -> Result<String> {
-    check_input(path).await?;
-
-    let output = Command::new("transmission-show")
-        .arg("-m")
-        .arg(path.as_os_str())
-        .output()
-        .context("transmission-show lanuch failed")?;
-
-    if !output.status.success() {
-        bail!("transsmission return err");
-    }
-
-    let magnet = Magnet::new(std::str::from_utf8(&output.stdout)?)
-        .map_err(|_| anyhow!("magnet parse fail"))?;
-    let xt = magnet.xt.ok_or_else(|| anyhow!("magnet parse fail"))?;
-    Ok("magnet:?xt=urn:btih:".to_string() + &xt)
-}
-
-pub(crate) async fn get_torrent_name_async(path: &Path) -> Result<String> {
-    check_input(path).await?;
-
-    let get_name = Command::new("transmission-show")
-        .arg(path.as_os_str())
-        .output()
-        .context("transmission-show lanuch failed")?;
-
-    if !get_name.status.success() {
-        bail!("transmission-show return err");
-    }
-
-    let mut name = std::str::from_utf8(&get_name.stdout)?
-        .lines()
-        .next()
-        .ok_or_else(|| anyhow!("transmission-show stdout empty"))?;
-
-    name = if name.trim_start().starts_with("Name:") {
-        name.trim_start()
-            .strip_prefix("Name:")
-            .unwrap()
-            .trim_start()
-    } else {
-        "Unknown"
-    };
-
-    Ok(name.to_string())
-}
-
-pub(crate) async fn get_torrent_summary_async(path: &Path) -> Result<String> {
-    check_input(path).await?;
-
-    let output = Command::new("aria2c")
-        .arg("-S")
-        .arg(path.as_os_str())
-        .output()
-        .context("aria2c lanuch failed")?;
-
-    if !output.status.success() {
-        bail!("aria2c return err");
-    }
-    let res = std::str::from_utf8(&output.stdout)?;
-    let mut lines = res.lines();
-
-    for line in &mut lines {
-        if line.trim_start().starts_with("Total Length:") {
-            let mut summary = line
-                .trim_start()
-                .strip_prefix("Total Length:")
-                .map(str::trim_start)
-                .map(|s| {
-                    s.split_once('(')
-                        .ok_or_else(|| anyhow!("cannot find torrent summary in split("))
-                })
-                .expect("checked, should start with Total Length...")?
-                .1
-                .trim();
-            if summary.ends_with(')') {
-                summary = summary.strip_suffix(')').unwrap();
-            }
-
-            let mut summary = summary.to_owned();
-            summary.retain(|x| x != ',');
-            let num: u128 = summary.parse()?;
-            let summary = to_iec(num);
-
-            return Ok(summary);
-        } else if line.starts_with("Files") {
-            break;
-        }
-    }
-
-    bail!("fail to find summary in transmission-show")
-}
-
 pub(crate) fn base32_hex(content: &str) -> Result<String> {
     let bytes = BASE32_NOPAD.decode(content.as_bytes())?;
     Ok(HEXUPPER.encode(&bytes))
 }
 
 pub(crate) async fn magnet_info(hash_hex: &str) -> Result<String> {
-    let dest = format!("{}/{}.torrent", ROOT_FOLDER, hash_hex.to_ascii_uppercase());
-    let dest = Path::new(&dest);
-
-    defer! {
-        if dest.exists() {
-            let _ = std::fs::remove_file(&dest);
-        }
-    }
-
-    let hash = hash_hex.to_ascii_uppercase();
-    let url = format!("https://itorrents.org/torrent/{}.torrent", hash);
-    let response = reqwest::get(&url).await?;
-
-    log::info!("url: {} \n-> {}", url, response.status());
-
-    if response.status().is_success() {
-        let mut file = TokioFile::create(dest).await?;
-        file.write_all(&response.bytes().await?).await?;
-    }
+    let (meta, url) = fetch_torrent(hash_hex).await?;
+    let link = url
+        .map(|url| format!("\t|\t<u><a href=\"{}\">种子文件</a></u> ", url))
+        .unwrap_or_default();
     let res = format!(
-        "<code>{}</code>\t|\t<u><a href=\"{}\">种子文件</a></u> \n---\n总计: {}",
-        get_torrent_name_async(dest).await?,
-        url,
-        get_torrent_summary_async(dest).await?,
+        "<code>{}</code>{}\n---\n{} 种子, 共 {} 个文件, 总计: {}",
+        escape(meta.name()),
+        link,
+        meta.version,
+        meta.file_count(),
+        to_iec(meta.total_length()),
     );
     Ok(res)
 }
 
 lazy_static! {
-    static ref MAGNET_RE: Regex =
-        Regex::new(r"magnet:\?xt=urn:btih:([a-fA-F0-9]{40}|[a-zA-Z2-7]{32})").unwrap();
+    // captures `btih:<hash>` or `btmh:<multihash>`, i.e. everything after `urn:`
+    static ref MAGNET_RE: Regex = Regex::new(
+        r"magnet:\?xt=urn:(btih:(?:[a-fA-F0-9]{40}|[a-zA-Z2-7]{32})|btmh:1220[a-fA-F0-9]{64})"
+    )
+    .unwrap();
 }
 
 pub(crate) async fn all_magnet_from_text(text: &str) -> Option<Vec<String>> {
@@ -957,42 +1260,24 @@ pub(crate) async fn all_magnet_from_text(text: &str) -> Option<Vec<String>> {
 pub(crate) async fn write_all_to_file(output: &Path, bytes: &[u8]) -> Result<()> {
     let mut outfile = TokioFile::create(output).await?;
     outfile.write_all(bytes).await?;
+    outfile.flush().await?;
     Ok(())
 }
 
 pub(crate) async fn file_encoding(input: &Path) -> Result<String> {
     check_input(input).await?;
-    let output = Command::new("uchardet")
-        .arg(input.as_os_str())
-        .output()
-        .context("uchardet lanuch failed")?;
-
-    let res = std::str::from_utf8(&output.stdout)?;
-    Ok(res.to_string())
+    Ok(text_encoding::file_encoding(input).await?.to_string())
 }
 
+/// writes the file as utf8 without bom, returns a message instead when there is nothing to convert
 pub(crate) async fn file_to_utf8(input: &Path, output: &Path) -> Result<String> {
     check_input_output(input, output).await?;
 
-    let out = Command::new("uchardet")
-        .arg(input.as_os_str())
-        .output()
-        .context("uchardet lanuch failed")?;
-    let res = std::str::from_utf8(&out.stdout)?;
-
-    if res.trim().to_lowercase() == "unknown" {
-        return Ok("看不出来啥编码...".to_string());
-    } else if res.to_lowercase().trim() == "ascii".to_lowercase()
-        || res.to_lowercase().trim() == "utf-8".to_lowercase()
-    {
+    if text_encoding::file_encoding(input).await?.is_plain_utf8() {
         return Ok("已经是正常的编码，没有必要再转换了...".to_string());
     }
-
-    let _output = Command::new("utf8ft")
-        .arg(input.as_os_str())
-        .arg(output.as_os_str())
-        .output()
-        .context("utf8ft lanuch failed")?;
+    let content = read_utf8(input).await?;
+    write_all_to_file(output, content.as_bytes()).await?;
 
     Ok(Default::default())
 }
@@ -1000,9 +1285,7 @@ pub(crate) async fn file_to_utf8(input: &Path, output: &Path) -> Result<String>
 pub(crate) async fn all_magnet_from_file(input: &Path, output: &Path) -> Result<()> {
     check_input_output(input, output).await?;
 
-    let mut file = open_without_bom(input).await?;
-    let mut content = String::new();
-    file.read_to_string(&mut content).await?;
+    let content = read_utf8(input).await?;
     let mut iter = MAGNET_RE.captures_iter(&content);
     let mut list = Vec::new();
     for capture in iter.by_ref() {
@@ -1014,7 +1297,7 @@ pub(crate) async fn all_magnet_from_file(input: &Path, output: &Path) -> Result<
     } else {
         let mut res = String::new();
         list.iter()
-            .for_each(|hash| res.push_str(&format!("magnet:?xt=urn:btih:{}\n", hash)));
+            .for_each(|urn| res.push_str(&format!("magnet:?xt=urn:{}\n", urn)));
         write_all_to_file(output, res.as_bytes()).await?;
     }
     Ok(())
@@ -1027,9 +1310,7 @@ lazy_static! {
 pub(crate) async fn all_ed2k_from_file(input: &Path, output: &Path) -> Result<()> {
     check_input_output(input, output).await?;
 
-    let mut file = open_without_bom(input).await?;
-    let mut content = String::new();
-    file.read_to_string(&mut content).await?;
+    let content = read_utf8(input).await?;
     // let mut iter =
     let mut res = String::new();
     for mat in ED2K_RE.find_iter(&content) {
@@ -1044,3 +1325,134 @@ pub(crate) async fn all_ed2k_from_file(input: &Path, output: &Path) -> Result<()
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_parser::parse_line;
+
+    const HASH: &str = "0123456789ABCDEF0123456789ABCDEF01234567";
+
+    fn record(name: &str, folders: &str) -> Sha1Line {
+        let line = format!("115://{}|1|{}|{}{}", name, HASH, HASH, folders);
+        parse_line(&line, 1).unwrap().unwrap()
+    }
+
+    fn names(entity: &Sha1Entity) -> Vec<&str> {
+        entity.files.iter().map(|f| f.name()).collect()
+    }
+
+    #[test]
+    fn tree_merges_folders_seen_again() {
+        let mut tree = TreeBuilder::default();
+        tree.push_line(record("a", "|root|sub"));
+        tree.push_line(record("b", "|root"));
+        tree.push_line(record("c", "|root|sub"));
+        tree.push_line(record("d", "|other"));
+        tree.push_line(record("loose", ""));
+        let (roots, files) = tree.into_roots();
+        assert!(files.is_empty());
+        assert_eq!(roots.len(), 2);
+        assert_eq!(
+            (roots[0].dir_name.as_str(), names(&roots[0])),
+            ("root", vec!["b"])
+        );
+        assert_eq!(roots[0].dirs.len(), 1);
+        assert_eq!(names(&roots[0].dirs[0]), ["a", "c"]);
+        assert_eq!(roots[1].dir_name, "other");
+    }
+
+    #[test]
+    fn tree_keeps_loose_files_in_the_root() {
+        let mut tree = TreeBuilder::keeping_loose_files();
+        tree.push_line(record("a", ""));
+        tree.push_line(record("b", "|root"));
+        tree.push_line(record("c", ""));
+        tree.push_folder(&["root", "empty"], None);
+        let (roots, files) = tree.into_roots();
+        assert_eq!(
+            files.iter().map(|f| f.name()).collect::<Vec<_>>(),
+            ["a", "c"]
+        );
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].dirs[0].dir_name, "empty");
+    }
+
+    #[test]
+    fn entity_lines_skip_nameless_and_synthetic_roots() {
+        let link = format!("a|1|{}|{}|note", HASH, HASH);
+        let json = format!(
+            r#"{{"dir_name": "", "files": ["{0}"], "dirs": [
+                {{"dir_name": "x", "files": ["{0}"], "dirs": [{{"dir_name": "e", "files": [], "dirs": []}}]}}]}}"#,
+            link
+        );
+        let entity = parse_sha1_json(json.as_bytes()).unwrap().0;
+        let loose = format!("115://a|1|{}|{}||note\n", HASH, HASH);
+        let nested = format!("115://a|1|{}|{}|x||note\n", HASH, HASH);
+        assert_eq!(entity_lines(&entity, false), loose.clone() + &nested);
+        assert_eq!(entity_lines(&entity, true), loose + &nested + "||||x|e\n");
+
+        let roots = format!(
+            r#"[{{"dir_name": "x", "files": ["{0}"], "dirs": []}},
+                {{"dir_name": "y", "files": [], "dirs": []}}]"#,
+            link
+        );
+        let entity = parse_sha1_json(roots.as_bytes()).unwrap().0;
+        assert_eq!(entity_lines(&entity, true), nested + "||||y\n");
+    }
+
+    async fn round_trip(lines: &str, folder_lines: bool) -> String {
+        let dir = std::env::temp_dir().join(format!(
+            "round_trip_{}_{}",
+            std::process::id(),
+            folder_lines
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (txt, json, back) = (dir.join("a.txt"), dir.join("a.json"), dir.join("b.txt"));
+        std::fs::write(&txt, lines).unwrap();
+        line2json(&txt, &json, true).await.unwrap();
+        json2line(&json, &back, folder_lines).await.unwrap();
+        let res = std::fs::read_to_string(&back).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        res
+    }
+
+    #[tokio::test]
+    async fn round_trip_gives_the_list_back() {
+        let lower = HASH.to_lowercase();
+        let link = |name: &str, hash: &str, rest: &str| {
+            format!("115://{}|1|{}|{}{}\n", name, hash, hash, rest)
+        };
+        let lines = [
+            link("a", &lower, "|root|sub"),
+            link("loose", &lower, ""),
+            "||||other|empty\n".to_owned(),
+            link("b", HASH, "|root"),
+            link("c", &lower, "|root|sub||note|x"),
+            "||||root|sub\n".to_owned(),
+            link("d", &lower, "|other"),
+        ]
+        .concat();
+        assert_eq!(round_trip(&lines, true).await, lines);
+
+        // without folder lines only those are left out
+        let files: String = lines
+            .lines()
+            .filter(|l| !l.starts_with("||||"))
+            .map(|l| l.to_owned() + "\n")
+            .collect();
+        assert_eq!(round_trip(&lines, false).await, files);
+    }
+
+    #[test]
+    fn hashes_keep_their_case() {
+        let lower: Sha1Hash = HASH.to_lowercase().parse().unwrap();
+        let upper: Sha1Hash = HASH.parse().unwrap();
+        assert_eq!(lower, upper);
+        assert_eq!(lower.to_string(), HASH.to_lowercase());
+        assert_eq!(lower.to_uppercase(), HASH);
+        let mixed: Sha1Hash = HASH.replacen('A', "a", 1).parse().unwrap();
+        assert_eq!(mixed.to_string(), HASH);
+    }
+}
@@ -0,0 +1,284 @@
+///
+/// where torrent metadata for a magnet link comes from
+///
+use anyhow::{bail, Context, Result};
+use lazy_static::lazy_static;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+
+use crate::global::TORRENT_CACHE_FOLDER;
+use crate::torrent::TorrentMeta;
+
+const DEFAULT_MIRRORS: &str = "https://itorrents.org/torrent/{hash}.torrent";
+
+type FetchFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<Fetched>>> + Send + 'a>>;
+
+/// raw .torrent bytes and the url they can be downloaded from, if any
+pub(crate) struct Fetched {
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) url: Option<String>,
+}
+
+pub(crate) trait MetadataProvider: Send + Sync {
+    fn name(&self) -> String;
+    /// `hash` is the uppercase hex v1 infohash, `Ok(None)` if this provider does not have it
+    fn fetch<'a>(&'a self, hash: &'a str) -> FetchFuture<'a>;
+}
+
+/// a torrent cache site, `{hash}` / `{hash_lower}` in the template are replaced by the infohash
+pub(crate) struct HttpProvider {
+    template: String,
+}
+
+impl HttpProvider {
+    pub(crate) fn new(template: impl Into<String>) -> Self {
+        Self {
+            template: template.into(),
+        }
+    }
+}
+
+impl MetadataProvider for HttpProvider {
+    fn name(&self) -> String {
+        self.template.to_owned()
+    }
+
+    fn fetch<'a>(&'a self, hash: &'a str) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let url = self
+                .template
+                .replace("{hash}", hash)
+                .replace("{hash_lower}", &hash.to_ascii_lowercase());
+            let response = reqwest::get(&url).await?;
+            log::info!("url: {} \n-> {}", url, response.status());
+            if !response.status().is_success() {
+                return Ok(None);
+            }
+            Ok(Some(Fetched {
+                bytes: response.bytes().await?.to_vec(),
+                url: Some(url),
+            }))
+        })
+    }
+}
+
+/// a directory of `<infohash>.torrent` files
+pub(crate) struct LocalDirProvider {
+    dir: PathBuf,
+}
+
+impl LocalDirProvider {
+    pub(crate) fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    async fn read(&self, filename: String) -> Result<Option<Vec<u8>>> {
+        let path = self.dir.join(filename);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = tokio::fs::read(&path)
+            .await
+            .context(format!("failed to read {}", path.to_string_lossy()))?;
+        Ok(Some(bytes))
+    }
+}
+
+impl MetadataProvider for LocalDirProvider {
+    fn name(&self) -> String {
+        self.dir.to_string_lossy().to_string()
+    }
+
+    fn fetch<'a>(&'a self, hash: &'a str) -> FetchFuture<'a> {
+        Box::pin(async move {
+            for filename in [
+                format!("{}.torrent", hash),
+                format!("{}.torrent", hash.to_ascii_lowercase()),
+            ] {
+                if let Some(bytes) = self.read(filename).await? {
+                    return Ok(Some(Fetched { bytes, url: None }));
+                }
+            }
+            Ok(None)
+        })
+    }
+}
+
+lazy_static! {
+    /// `TORRENT_DIR`: a local folder tried first,
+    /// `TORRENT_MIRRORS`: comma separated url templates tried in order
+    static ref PROVIDERS: Vec<Box<dyn MetadataProvider>> = {
+        let mut list: Vec<Box<dyn MetadataProvider>> = Vec::new();
+        if let Some(dir) = std::env::var_os("TORRENT_DIR") {
+            list.push(Box::new(LocalDirProvider::new(dir)));
+        }
+        let mirrors =
+            std::env::var("TORRENT_MIRRORS").unwrap_or_else(|_| DEFAULT_MIRRORS.to_string());
+        for template in mirrors.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            list.push(Box::new(HttpProvider::new(template)));
+        }
+        list
+    };
+}
+
+/// torrents fetched before, the url one came from is kept next to it in `<infohash>.url`
+struct TorrentCache {
+    dir: PathBuf,
+}
+
+impl TorrentCache {
+    fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, hash: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", hash, extension))
+    }
+
+    /// the cached torrent and its url, an entry that no longer decodes is removed
+    async fn get(&self, hash: &str) -> Result<Option<(TorrentMeta, Option<String>)>> {
+        let bytes = match LocalDirProvider::new(&self.dir).fetch(hash).await? {
+            Some(fetched) => fetched.bytes,
+            None => return Ok(None),
+        };
+        match TorrentMeta::from_bytes(&bytes) {
+            Ok(meta) => {
+                let url = tokio::fs::read_to_string(self.path(hash, "url"))
+                    .await
+                    .ok()
+                    .map(|url| url.trim().to_owned())
+                    .filter(|url| !url.is_empty());
+                Ok(Some((meta, url)))
+            }
+            Err(_) => {
+                let _ = std::fs::remove_file(self.path(hash, "torrent"));
+                let _ = std::fs::remove_file(self.path(hash, "url"));
+                Ok(None)
+            }
+        }
+    }
+
+    async fn put(&self, hash: &str, fetched: &Fetched) {
+        if let Err(e) = tokio::fs::write(self.path(hash, "torrent"), &fetched.bytes).await {
+            log::warn!("failed to cache torrent {}: {}", hash, e);
+            return;
+        }
+        let url_path = self.path(hash, "url");
+        match &fetched.url {
+            Some(url) => {
+                if let Err(e) = tokio::fs::write(&url_path, url).await {
+                    log::warn!("failed to cache the url of torrent {}: {}", hash, e);
+                }
+            }
+            None => {
+                let _ = std::fs::remove_file(url_path);
+            }
+        }
+    }
+}
+
+/// look the torrent up in the on-disk cache, then every provider in order.
+/// whatever a provider returns must decode and match the infohash, otherwise the next one is tried
+pub(crate) async fn fetch_torrent(hash_hex: &str) -> Result<(TorrentMeta, Option<String>)> {
+    let hash = hash_hex.to_ascii_uppercase();
+
+    let cache = TorrentCache::new(TORRENT_CACHE_FOLDER);
+    if let Some(cached) = cache.get(&hash).await? {
+        return Ok(cached);
+    }
+
+    for provider in PROVIDERS.iter() {
+        let fetched = match provider.fetch(&hash).await {
+            Ok(Some(fetched)) => fetched,
+            Ok(None) => continue,
+            Err(e) => {
+                log::warn!("provider {} failed: {}", provider.name(), e);
+                continue;
+            }
+        };
+
+        let meta = match TorrentMeta::from_bytes(&fetched.bytes) {
+            Ok(meta) => meta,
+            Err(e) => {
+                log::warn!(
+                    "provider {} returned an invalid torrent: {}",
+                    provider.name(),
+                    e
+                );
+                continue;
+            }
+        };
+        let same_hash = meta
+            .info_hash
+            .as_deref()
+            .map(|h| h.eq_ignore_ascii_case(&hash))
+            .unwrap_or(false);
+        if !same_hash {
+            log::warn!("provider {} returned a different torrent", provider.name());
+            continue;
+        }
+
+        cache.put(&hash, &fetched).await;
+        return Ok((meta, fetched.url));
+    }
+
+    bail!("torrent {} not found in any provider", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "7FAF75B2447F88700C68F1ECEDA713CD90A0127A";
+    const TORRENT: &str = "d8:announce4:http4:infod6:lengthi5e4:name5:a.txt12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn local_dir_finds_torrents_by_either_case() {
+        let dir = temp_dir("local_dir_provider");
+        std::fs::write(
+            dir.join(format!("{}.torrent", HASH.to_ascii_lowercase())),
+            TORRENT,
+        )
+        .unwrap();
+        let provider = LocalDirProvider::new(&dir);
+
+        let fetched = provider.fetch(HASH).await.unwrap().unwrap();
+        assert_eq!(fetched.url, None);
+        let meta = TorrentMeta::from_bytes(&fetched.bytes).unwrap();
+        assert_eq!(
+            meta.info_hash.as_deref(),
+            Some(HASH.to_ascii_lowercase().as_str())
+        );
+        assert!(provider.fetch(&"0".repeat(40)).await.unwrap().is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cache_keeps_the_url() {
+        let dir = temp_dir("torrent_cache");
+        let cache = TorrentCache::new(&dir);
+        let url = format!("https://example.org/{}.torrent", HASH);
+        let fetched = Fetched {
+            bytes: TORRENT.as_bytes().to_vec(),
+            url: Some(url.to_owned()),
+        };
+        cache.put(HASH, &fetched).await;
+        let (meta, cached_url) = cache.get(HASH).await.unwrap().unwrap();
+        assert_eq!(meta.total_length(), 5);
+        assert_eq!(cached_url, Some(url));
+
+        std::fs::write(cache.path(HASH, "torrent"), "not a torrent").unwrap();
+        assert!(cache.get(HASH).await.unwrap().is_none());
+        assert!(!cache.path(HASH, "torrent").exists());
+        assert!(!cache.path(HASH, "url").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
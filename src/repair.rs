@@ -0,0 +1,346 @@
+///
+/// putting back together link lines that went through chat apps and forums
+///
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::io::{check_input_output, read_utf8};
+use crate::line_parser::parse_line;
+use crate::parsers::write_all_to_file;
+
+const PREFIX: &str = "115://";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum Fix {
+    CarriageReturn,
+    ZeroWidth,
+    /// `｜` instead of `|`
+    FullWidthSeparator,
+    DoubledPrefix,
+    MissingPrefix,
+    /// several links on one line
+    GluedLinks,
+    /// text in front of the first link
+    LeadingText,
+    StraySpaces,
+    HexCase,
+}
+
+impl fmt::Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Fix::CarriageReturn => "行尾回车符",
+            Fix::ZeroWidth => "零宽字符",
+            Fix::FullWidthSeparator => "全角分隔符",
+            Fix::DoubledPrefix => "重复的 115://",
+            Fix::MissingPrefix => "缺少 115://",
+            Fix::GluedLinks => "多个链接连在一行",
+            Fix::LeadingText => "链接前的多余文字",
+            Fix::StraySpaces => "多余空格",
+            Fix::HexCase => "sha1 大小写",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn is_zero_width(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}'
+    )
+}
+
+fn is_hex40(s: &str) -> bool {
+    s.len() == 40 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// a single link without its prefix, fields trimmed and hashes uppercase
+fn repair_fields(link: &str, fixes: &mut Vec<Fix>) -> String {
+    let mut fields: Vec<String> = link.split('|').map(str::to_owned).collect();
+    for field in fields.iter_mut() {
+        let trimmed = field.trim();
+        if trimmed.len() != field.len() {
+            *field = trimmed.to_owned();
+            push_once(fixes, Fix::StraySpaces);
+        }
+    }
+    for i in [2, 3] {
+        if let Some(field) = fields.get_mut(i) {
+            // spaces inside a hash never belong there
+            let compact: String = field.chars().filter(|c| !c.is_whitespace()).collect();
+            if compact.len() != field.len() && is_hex40(&compact) {
+                *field = compact;
+                push_once(fixes, Fix::StraySpaces);
+            }
+            if is_hex40(field) && field.chars().any(|c| c.is_ascii_lowercase()) {
+                *field = field.to_ascii_uppercase();
+                push_once(fixes, Fix::HexCase);
+            }
+        }
+    }
+    fields.join("|")
+}
+
+fn push_once(fixes: &mut Vec<Fix>, fix: Fix) {
+    if !fixes.contains(&fix) {
+        fixes.push(fix);
+    }
+}
+
+/// whether the text reads like a link once it gets a prefix
+fn looks_like_link(s: &str) -> bool {
+    let fields: Vec<&str> = s.split('|').collect();
+    fields.len() >= 4
+        && fields[1].trim().chars().all(|c| c.is_ascii_digit())
+        && is_hex40(
+            &fields[2]
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect::<String>(),
+        )
+}
+
+/// the links a raw line holds and what had to change, the line is returned as is when it holds none
+pub(crate) fn repair_line(raw: &str) -> (Vec<String>, Vec<Fix>) {
+    let mut fixes = Vec::new();
+    let mut line = raw.to_owned();
+
+    if line.ends_with('\r') {
+        line = line.trim_end_matches('\r').to_owned();
+        fixes.push(Fix::CarriageReturn);
+    }
+    if line.chars().any(is_zero_width) {
+        line.retain(|c| !is_zero_width(c));
+        fixes.push(Fix::ZeroWidth);
+    }
+    if line.contains('｜') {
+        line = line.replace('｜', "|");
+        fixes.push(Fix::FullWidthSeparator);
+    }
+    let doubled = format!("{}{}", PREFIX, PREFIX);
+    while line.contains(&doubled) {
+        line = line.replace(&doubled, PREFIX);
+        push_once(&mut fixes, Fix::DoubledPrefix);
+    }
+
+    let mut pieces: Vec<&str> = line
+        .split(PREFIX)
+        .filter(|piece| !piece.trim().is_empty())
+        .collect();
+    let had_prefix = line.trim_start().starts_with(PREFIX);
+    if pieces.is_empty() {
+        return (vec![line], fixes);
+    }
+    if !had_prefix && !looks_like_link(pieces[0]) {
+        // not a link at all
+        if pieces.len() == 1 {
+            return (vec![line], fixes);
+        }
+        pieces.remove(0);
+        fixes.push(Fix::LeadingText);
+    } else if !had_prefix {
+        fixes.push(Fix::MissingPrefix);
+    } else if line.starts_with(char::is_whitespace) {
+        fixes.push(Fix::StraySpaces);
+    }
+    if pieces.len() > 1 {
+        fixes.push(Fix::GluedLinks);
+    }
+
+    let links = pieces
+        .into_iter()
+        .map(|piece| format!("{}{}", PREFIX, repair_fields(piece, &mut fixes)))
+        .collect();
+    (links, fixes)
+}
+
+pub(crate) struct RepairReport {
+    /// line number, what changed, the line before and after
+    changes: Vec<(usize, Vec<Fix>, String, String)>,
+    counts: BTreeMap<Fix, usize>,
+    pub(crate) valid: usize,
+    pub(crate) invalid: usize,
+}
+
+impl RepairReport {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// every changed line before and after
+    pub(crate) fn details(&self) -> String {
+        let mut res = String::new();
+        for (line_number, fixes, before, after) in &self.changes {
+            let fixes: Vec<String> = fixes.iter().map(Fix::to_string).collect();
+            res.push_str(&format!(
+                "第 {} 行: {}\n  原: {}\n  新: {}\n",
+                line_number,
+                fixes.join(", "),
+                before,
+                after.replace('\n', "\n      ")
+            ));
+        }
+        res
+    }
+}
+
+impl fmt::Display for RepairReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts: Vec<String> = self
+            .counts
+            .iter()
+            .map(|(fix, n)| format!("{} {} 行", fix, n))
+            .collect();
+        write!(
+            f,
+            "自动修复了 {} 行: {}\n修复后共 {} 个有效链接",
+            self.changes.len(),
+            counts.join(", "),
+            self.valid
+        )?;
+        if self.invalid != 0 {
+            write!(f, ", 仍有 {} 行无法识别", self.invalid)?;
+        }
+        Ok(())
+    }
+}
+
+/// repairs every line of an in-memory list, returns the new content
+pub(crate) fn repair_lines(content: &str) -> (String, RepairReport) {
+    let mut report = RepairReport {
+        changes: Vec::new(),
+        counts: BTreeMap::new(),
+        valid: 0,
+        invalid: 0,
+    };
+    let mut output = String::new();
+    for (i, raw) in content.split('\n').enumerate() {
+        let (links, fixes) = repair_line(raw);
+        for link in &links {
+            match parse_line(link, i + 1) {
+                Ok(Some(_)) => report.valid += 1,
+                Ok(None) => {}
+                Err(_) => report.invalid += 1,
+            }
+        }
+        let repaired = links.join("\n");
+        if !repaired.trim().is_empty() {
+            output.push_str(&repaired);
+            output.push('\n');
+        }
+        if !fixes.is_empty() && repaired != raw {
+            for fix in &fixes {
+                *report.counts.entry(*fix).or_default() += 1;
+            }
+            report.changes.push((
+                i + 1,
+                fixes,
+                raw.trim_end_matches('\r').to_owned(),
+                repaired,
+            ));
+        }
+    }
+    (output, report)
+}
+
+/// writes the repaired list to `output` and what changed to `log_output`
+pub(crate) async fn repair_file(
+    input: &Path,
+    output: &Path,
+    log_output: &Path,
+) -> Result<RepairReport> {
+    check_input_output(input, output).await?;
+
+    let (content, report) = repair_lines(&read_utf8(input).await?);
+
+    if !report.is_empty() {
+        write_all_to_file(output, content.as_bytes()).await?;
+        write_all_to_file(log_output, report.details().as_bytes()).await?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789ABCDEF0123456789ABCDEF01234567";
+
+    fn link(name: &str) -> String {
+        format!("115://{}|1|{}|{}", name, HASH, HASH)
+    }
+
+    #[test]
+    fn every_fix() {
+        let lower = HASH.to_ascii_lowercase();
+        let spaced = format!("{} {}", &HASH[..20], &HASH[20..]);
+        let cases = [
+            (
+                format!("{}\r", link("a")),
+                vec![link("a")],
+                vec![Fix::CarriageReturn],
+            ),
+            (
+                link("a").replacen('|', "|\u{200B}", 1),
+                vec![link("a")],
+                vec![Fix::ZeroWidth],
+            ),
+            (
+                link("a").replace('|', "｜"),
+                vec![link("a")],
+                vec![Fix::FullWidthSeparator],
+            ),
+            (
+                format!("115://{}", link("a")),
+                vec![link("a")],
+                vec![Fix::DoubledPrefix],
+            ),
+            (
+                link("a")[6..].to_owned(),
+                vec![link("a")],
+                vec![Fix::MissingPrefix],
+            ),
+            (
+                format!("{}{}", link("a"), link("b")),
+                vec![link("a"), link("b")],
+                vec![Fix::GluedLinks],
+            ),
+            (
+                format!("看这个 {}", link("a")),
+                vec![link("a")],
+                vec![Fix::LeadingText],
+            ),
+            (
+                format!(" 115://a | 1 |{}|{}", spaced, HASH),
+                vec![link("a")],
+                vec![Fix::StraySpaces],
+            ),
+            (
+                format!("115://a|1|{}|{}", lower, lower),
+                vec![link("a")],
+                vec![Fix::HexCase],
+            ),
+        ];
+        for (raw, links, fixes) in cases {
+            assert_eq!(repair_line(&raw), (links, fixes), "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn good_lines_are_left_alone() {
+        for raw in [
+            format!("{}|dir|sub", link("a")),
+            "不是链接 | 1".to_owned(),
+            String::new(),
+        ] {
+            assert_eq!(repair_line(&raw), (vec![raw.to_owned()], Vec::new()));
+        }
+        let content = format!("{}\n说明\n{}\n", link("a"), link("b"));
+        let (output, report) = repair_lines(&content);
+        assert!(report.is_empty());
+        assert_eq!(output, content);
+        assert_eq!(report.valid, 2);
+    }
+}
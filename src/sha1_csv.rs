@@ -0,0 +1,253 @@
+///
+/// sha1 lists as csv / tsv for spreadsheets, and edited sheets back into 115 links
+///
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::io::{check_input, check_input_output, read_utf8};
+use crate::line_parser::{parse_line, parse_lines, Sha1LineReader};
+use crate::parsers::{entity_lines, to_iec, FileRepr, Sha1Entity};
+
+const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
+
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    name: &'a str,
+    size: u64,
+    human_size: String,
+    sha1: String,
+    block_sha1: String,
+    folder: String,
+    extension: String,
+}
+
+#[derive(Deserialize)]
+struct CsvRecord {
+    name: String,
+    size: String,
+    sha1: String,
+    block_sha1: String,
+    #[serde(default)]
+    folder: String,
+}
+
+fn csv_writer(output: &Path, tsv: bool) -> Result<csv::Writer<std::fs::File>> {
+    use std::io::Write;
+    let mut file = std::fs::File::create(output).context(format!(
+        "failed to create the output file:{}",
+        output.to_string_lossy(),
+    ))?;
+    // without the bom excel reads utf8 as the local code page
+    file.write_all(UTF8_BOM)?;
+    Ok(csv::WriterBuilder::new()
+        .delimiter(if tsv { b'\t' } else { b',' })
+        .from_writer(file))
+}
+
+fn write_row(
+    writer: &mut csv::Writer<std::fs::File>,
+    file: &FileRepr,
+    folders: &[String],
+) -> Result<()> {
+    writer.serialize(CsvRow {
+        name: file.name(),
+        size: file.size(),
+        human_size: to_iec(file.size()),
+        sha1: file.sha1().to_string(),
+        block_sha1: file.sha1_block().to_string(),
+        folder: folders.join("/"),
+        extension: file.extension(),
+    })?;
+    Ok(())
+}
+
+/// number of rows written, invalid lines are left out
+pub(crate) async fn line2csv(input: &Path, output: &Path, tsv: bool) -> Result<usize> {
+    check_input_output(input, output).await?;
+
+    let mut reader = Sha1LineReader::open(input).await?;
+    let mut writer = csv_writer(output, tsv)?;
+    let mut count = 0;
+    while let Some(parsed) = reader.next_line().await? {
+        if let Ok(record) = parsed {
+            write_row(&mut writer, &record.file, &record.folders)?;
+            count += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+pub(crate) fn json2csv(entity: &Sha1Entity, output: &Path, tsv: bool) -> Result<usize> {
+    if output.exists() {
+        bail!("output path taken");
+    }
+
+    let lines = entity_lines(entity, false);
+    let mut writer = csv_writer(output, tsv)?;
+    let mut count = 0;
+    for record in parse_lines(&lines).flatten() {
+        write_row(&mut writer, &record.file, &record.folders)?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+/// turn a sheet back into 115 links, returns the number of links and why each skipped row was
+pub(crate) async fn csv2line(input: &Path, output: &Path) -> Result<(usize, Vec<String>)> {
+    check_input_output(input, output).await?;
+
+    // excel saves sheets in the system code page
+    let content = read_utf8(input).await?;
+    let (res, skipped) = csv2line_mem(&content)?;
+    if res.is_empty() {
+        bail!("no valid row in {}", input.to_string_lossy());
+    }
+    std::fs::write(output, &res)?;
+    Ok((res.lines().count(), skipped))
+}
+
+/// the links of an in-memory sheet and why each skipped row was, rows numbered as in the sheet
+pub(crate) fn csv2line_mem(content: &str) -> Result<(String, Vec<String>)> {
+    let header = content.lines().next().unwrap_or_default();
+    let delimiter = if header.contains('\t') { b'\t' } else { b',' };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+
+    let mut res = String::new();
+    let mut skipped = Vec::new();
+    for (i, row) in reader.deserialize::<CsvRecord>().enumerate() {
+        // the header is row 1
+        let row_number = i + 2;
+        let mut skip = |reason: &str| skipped.push(format!("第 {} 行: {}", row_number, reason));
+        let row = match row {
+            Ok(row) => row,
+            Err(e) => {
+                log::warn!("invalid csv row {}: {}", row_number, e);
+                skip("格式不正确");
+                continue;
+            }
+        };
+
+        // a pipe would end the name or add a folder, newlines end the link
+        let name = row.name.trim();
+        if name.contains('|') {
+            skip("名称包含 |");
+            continue;
+        }
+        if row.folder.contains('|') {
+            skip("目录包含 |");
+            continue;
+        }
+        if row.name.contains(['\n', '\r']) || row.folder.contains(['\n', '\r']) {
+            skip("包含换行");
+            continue;
+        }
+
+        let mut line = format!(
+            "115://{}|{}|{}|{}",
+            name,
+            row.size.trim(),
+            row.sha1.trim(),
+            row.block_sha1.trim()
+        );
+        for folder in row.folder.split('/').filter(|f| !f.is_empty()) {
+            line.push('|');
+            line.push_str(folder);
+        }
+
+        match parse_line(&line, row_number) {
+            Ok(Some(_)) => {
+                res.push_str(&line);
+                res.push('\n');
+            }
+            _ => {
+                log::warn!("invalid csv row {}: {}", row_number, line);
+                skip("不是有效的 115 链接");
+            }
+        }
+    }
+
+    Ok((res, skipped))
+}
+
+/// the first line looks like the header `line2csv` writes
+pub(crate) async fn is_sha1_csv(input: &Path) -> Result<bool> {
+    check_input(input).await?;
+    let content = read_utf8(input).await?;
+    let header = content.lines().next().unwrap_or_default().to_lowercase();
+    Ok(["name", "size", "sha1", "block_sha1"]
+        .iter()
+        .all(|col| header.contains(col)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789ABCDEF0123456789ABCDEF01234567";
+
+    #[tokio::test]
+    async fn sheets_round_trip() {
+        let dir = std::env::temp_dir().join(format!("sha1_csv_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let lines: String = [
+            "a,b.txt|1|{h}|{h}|x,y|z",
+            "say \"hi\".mkv|2|{h}|{h}",
+            "中文 名.mp4|3|{h}|{h}|中文",
+        ]
+        .iter()
+        .map(|line| format!("115://{}\n", line.replace("{h}", HASH)))
+        .collect();
+        let input = dir.join("list.txt");
+        std::fs::write(&input, &lines).unwrap();
+
+        for (tsv, name) in [(false, "list.csv"), (true, "list.tsv")] {
+            let sheet = dir.join(name);
+            assert_eq!(line2csv(&input, &sheet, tsv).await.unwrap(), 3);
+            let bytes = std::fs::read(&sheet).unwrap();
+            assert!(bytes.starts_with(UTF8_BOM));
+            let text = String::from_utf8_lossy(&bytes);
+            assert!(text.contains("\"say \"\"hi\"\".mkv\""));
+            if !tsv {
+                assert!(text.contains("\"a,b.txt\""));
+            }
+
+            let output = dir.join(format!("{}.txt", name));
+            let (count, skipped) = csv2line(&sheet, &output).await.unwrap();
+            assert_eq!((count, skipped.len()), (3, 0));
+            assert_eq!(std::fs::read_to_string(&output).unwrap(), lines);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pipes_are_rejected_by_row() {
+        let sheet = format!(
+            "name,size,sha1,block_sha1,folder\n\
+             ok,1,{h},{h},a/b\n\
+             \"a|b\",1,{h},{h},\n\
+             c,1,{h},{h},\"x|y\"\n\
+             \"line\nbreak\",1,{h},{h},\n\
+             d,1,nope,{h},\n",
+            h = HASH
+        );
+        let (res, skipped) = csv2line_mem(&sheet).unwrap();
+        assert_eq!(res, format!("115://ok|1|{h}|{h}|a|b\n", h = HASH));
+        assert_eq!(
+            skipped,
+            [
+                "第 3 行: 名称包含 |",
+                "第 4 行: 目录包含 |",
+                "第 5 行: 包含换行",
+                "第 6 行: 不是有效的 115 链接",
+            ]
+        );
+    }
+}
@@ -0,0 +1,450 @@
+///
+/// the json shapes 115 sha1 lists are shared in, all normalised into `Sha1Entity`
+///
+/// - standard: `{"dir_name": "..", "files": ["name|size|sha1|block_sha1"], "dirs": [..]}`
+/// - flat: `[{"name": "..", "size": 1, "sha1": "..", "preid": "..", "path": "a/b"}]`
+/// - entity array: `[{"dir_name": ..}, {"dir_name": ..}]`
+/// - children tree: `{"name": "..", "children": [..]}`, files carry `size`, `sha1` and `preid`
+///
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+use crate::parsers::{FileRepr, Sha1Entity, TreeBuilder};
+
+/// the name of the parent created when a list has more than one root
+pub(crate) const SYNTHETIC_ROOT: &str = "new_folder";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum JsonFormat {
+    Standard,
+    Flat,
+    EntityArray,
+    ChildrenTree,
+}
+
+impl JsonFormat {
+    pub(crate) const ALL: [JsonFormat; 4] = [
+        JsonFormat::Standard,
+        JsonFormat::Flat,
+        JsonFormat::EntityArray,
+        JsonFormat::ChildrenTree,
+    ];
+
+    /// callback code of the export button
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            JsonFormat::Standard => "js",
+            JsonFormat::Flat => "jf",
+            JsonFormat::EntityArray => "ja",
+            JsonFormat::ChildrenTree => "jc",
+        }
+    }
+
+    pub(crate) fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|f| f.code() == code)
+    }
+
+    /// appended to the file stem of an exported list
+    pub(crate) fn file_suffix(self) -> &'static str {
+        match self {
+            JsonFormat::Standard => "_标准",
+            JsonFormat::Flat => "_扁平",
+            JsonFormat::EntityArray => "_数组",
+            JsonFormat::ChildrenTree => "_树",
+        }
+    }
+}
+
+impl fmt::Display for JsonFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            JsonFormat::Standard => "标准格式",
+            JsonFormat::Flat => "扁平列表",
+            JsonFormat::EntityArray => "目录数组",
+            JsonFormat::ChildrenTree => "children 树",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumOrString {
+    Num(u64),
+    Str(String),
+}
+
+fn size_from_any<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+    match Option::<NumOrString>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(NumOrString::Num(n)) => Ok(Some(n)),
+        Some(NumOrString::Str(s)) => s.trim().parse().map(Some).map_err(D::Error::custom),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FlatEntry {
+    name: String,
+    #[serde(deserialize_with = "size_from_any")]
+    size: Option<u64>,
+    sha1: String,
+    #[serde(alias = "sha1_block", alias = "block_sha1")]
+    preid: String,
+    #[serde(default)]
+    path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TreeNode {
+    name: String,
+    #[serde(
+        default,
+        deserialize_with = "size_from_any",
+        skip_serializing_if = "Option::is_none"
+    )]
+    size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha1: Option<String>,
+    #[serde(
+        default,
+        alias = "sha1_block",
+        alias = "block_sha1",
+        skip_serializing_if = "Option::is_none"
+    )]
+    preid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    children: Option<Vec<TreeNode>>,
+}
+
+fn has_key(value: &Value, key: &str) -> bool {
+    value
+        .as_object()
+        .map(|o| o.contains_key(key))
+        .unwrap_or(false)
+}
+
+/// guess the shape from the top level value, `None` if it is none of the known ones
+pub(crate) fn detect_format(value: &Value) -> Option<JsonFormat> {
+    match value {
+        Value::Object(_) if has_key(value, "dir_name") => Some(JsonFormat::Standard),
+        Value::Object(_) if has_key(value, "children") => Some(JsonFormat::ChildrenTree),
+        Value::Array(list) if list.is_empty() => None,
+        Value::Array(list) if list.iter().any(|v| has_key(v, "dir_name")) => {
+            Some(JsonFormat::EntityArray)
+        }
+        Value::Array(list) if list.iter().any(|v| has_key(v, "children")) => {
+            Some(JsonFormat::ChildrenTree)
+        }
+        Value::Array(list) if list.iter().all(|v| has_key(v, "sha1")) => Some(JsonFormat::Flat),
+        _ => None,
+    }
+}
+
+/// a single root as is, several roots (or loose files) under a parent flagged as synthetic
+pub(crate) fn wrap_roots(mut roots: Vec<Sha1Entity>, files: Vec<FileRepr>) -> Result<Sha1Entity> {
+    if roots.is_empty() && files.is_empty() {
+        bail!("empty sha1 list");
+    }
+    if roots.len() == 1 && files.is_empty() {
+        return Ok(roots.remove(0));
+    }
+    let mut parent = Sha1Entity::new(SYNTHETIC_ROOT.to_owned());
+    parent.dirs = roots;
+    parent.files = files;
+    parent.synthetic = true;
+    Ok(parent)
+}
+
+fn file_from_parts(
+    name: String,
+    size: Option<u64>,
+    sha1: Option<String>,
+    preid: Option<String>,
+) -> Result<FileRepr> {
+    let size = size.ok_or_else(|| anyhow!("file without size: {}", name))?;
+    let sha1 = sha1.ok_or_else(|| anyhow!("file without sha1: {}", name))?;
+    let preid = preid.ok_or_else(|| anyhow!("file without preid: {}", name))?;
+    let sha1 = sha1
+        .parse()
+        .map_err(|_| anyhow!("invalid sha1 of {}: {}", name, sha1))?;
+    Ok(FileRepr::new(name, size, sha1, preid.as_str().into()))
+}
+
+fn from_flat(entries: Vec<FlatEntry>) -> Result<Sha1Entity> {
+    let mut tree = TreeBuilder::default();
+
+    for entry in entries {
+        let mut folders: Vec<&str> = entry
+            .path
+            .split(['/', '\\'])
+            .filter(|s| !s.is_empty())
+            .collect();
+        // some tools put the file itself at the end of the path
+        if folders.last() == Some(&entry.name.as_str()) {
+            folders.pop();
+        }
+        let folders: Vec<String> = folders.into_iter().map(str::to_owned).collect();
+
+        let file = file_from_parts(entry.name, entry.size, Some(entry.sha1), Some(entry.preid))?;
+        tree.push_file(&folders, file);
+    }
+
+    let (roots, loose) = tree.into_roots();
+    wrap_roots(roots, loose)
+}
+
+enum TreeItem {
+    Dir(Sha1Entity),
+    File(FileRepr),
+}
+
+fn from_tree_node(node: TreeNode) -> Result<TreeItem> {
+    match node.children {
+        Some(children) => {
+            let mut entity = Sha1Entity::new(node.name);
+            for child in children {
+                match from_tree_node(child)? {
+                    TreeItem::Dir(dir) => entity.dirs.push(dir),
+                    TreeItem::File(file) => entity.files.push(file),
+                }
+            }
+            Ok(TreeItem::Dir(entity))
+        }
+        // a leaf without hashes is an empty folder
+        None if node.sha1.is_none() => Ok(TreeItem::Dir(Sha1Entity::new(node.name))),
+        None => Ok(TreeItem::File(file_from_parts(
+            node.name, node.size, node.sha1, node.preid,
+        )?)),
+    }
+}
+
+fn from_tree(value: Value) -> Result<Sha1Entity> {
+    let nodes: Vec<TreeNode> = match value {
+        Value::Array(_) => serde_json::from_value(value)?,
+        _ => vec![serde_json::from_value(value)?],
+    };
+    let mut roots = Vec::new();
+    let mut loose = Vec::new();
+    for node in nodes {
+        match from_tree_node(node)? {
+            TreeItem::Dir(dir) => roots.push(dir),
+            TreeItem::File(file) => loose.push(file),
+        }
+    }
+    wrap_roots(roots, loose)
+}
+
+/// decode any known shape into a `Sha1Entity`, together with the shape it came in
+pub(crate) fn parse_sha1_json(bytes: &[u8]) -> Result<(Sha1Entity, JsonFormat)> {
+    let value: Value = serde_json::from_slice(bytes)?;
+    let format = detect_format(&value).ok_or_else(|| anyhow!("unknown sha1 json format"))?;
+    let entity = match format {
+        JsonFormat::Standard => serde_json::from_value(value)?,
+        JsonFormat::EntityArray => wrap_roots(serde_json::from_value(value)?, Vec::new())?,
+        JsonFormat::Flat => from_flat(serde_json::from_value(value)?)?,
+        JsonFormat::ChildrenTree => from_tree(value)?,
+    };
+    Ok((entity, format))
+}
+
+/// the roots a multi root format should list, the synthetic parent is dropped again
+pub(crate) fn top_level(entity: &Sha1Entity) -> (Vec<&Sha1Entity>, Vec<&FileRepr>) {
+    if entity.synthetic {
+        (entity.dirs.iter().collect(), entity.files.iter().collect())
+    } else {
+        (vec![entity], Vec::new())
+    }
+}
+
+fn flat_entry(file: &FileRepr, path: &str) -> FlatEntry {
+    FlatEntry {
+        name: file.name().to_owned(),
+        size: Some(file.size()),
+        sha1: file.sha1().to_string(),
+        preid: file.sha1_block().to_string(),
+        path: path.to_owned(),
+    }
+}
+
+fn push_flat(list: &mut Vec<FlatEntry>, entity: &Sha1Entity, parent: &str) {
+    let path = if parent.is_empty() {
+        entity.dir_name.to_owned()
+    } else {
+        format!("{}/{}", parent, entity.dir_name)
+    };
+    for file in &entity.files {
+        list.push(flat_entry(file, &path));
+    }
+    for dir in &entity.dirs {
+        push_flat(list, dir, &path);
+    }
+}
+
+fn file_node(file: &FileRepr) -> TreeNode {
+    TreeNode {
+        name: file.name().to_owned(),
+        size: Some(file.size()),
+        sha1: Some(file.sha1().to_string()),
+        preid: Some(file.sha1_block().to_string()),
+        children: None,
+    }
+}
+
+fn dir_node(entity: &Sha1Entity) -> TreeNode {
+    let mut children: Vec<TreeNode> = entity.dirs.iter().map(dir_node).collect();
+    children.extend(entity.files.iter().map(file_node));
+    TreeNode {
+        name: entity.dir_name.to_owned(),
+        size: None,
+        sha1: None,
+        preid: None,
+        children: Some(children),
+    }
+}
+
+/// serialise a list into the given shape
+pub(crate) fn export_sha1_json(entity: &Sha1Entity, format: JsonFormat) -> Result<Vec<u8>> {
+    let (dirs, files) = top_level(entity);
+    let res = match format {
+        JsonFormat::Standard => serde_json::to_vec(entity)?,
+        JsonFormat::EntityArray => {
+            // files outside of any folder go in a nameless entity, the way line2json writes them
+            let mut loose = Sha1Entity::new(String::new());
+            loose.files = files.into_iter().cloned().collect();
+            let mut list = Vec::new();
+            if !loose.files.is_empty() {
+                list.push(&loose);
+            }
+            list.extend(dirs);
+            serde_json::to_vec(&list)?
+        }
+        JsonFormat::Flat => {
+            let mut list: Vec<FlatEntry> = files.iter().map(|f| flat_entry(f, "")).collect();
+            for dir in dirs {
+                push_flat(&mut list, dir, "");
+            }
+            serde_json::to_vec(&list)?
+        }
+        JsonFormat::ChildrenTree => {
+            if entity.synthetic {
+                let mut nodes: Vec<TreeNode> = dirs.into_iter().map(dir_node).collect();
+                nodes.extend(files.into_iter().map(file_node));
+                serde_json::to_vec(&nodes)?
+            } else {
+                serde_json::to_vec(&dir_node(entity))?
+            }
+        }
+    };
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const HASH: &str = "0123456789ABCDEF0123456789ABCDEF01234567";
+
+    fn link(name: &str) -> String {
+        format!("{}|1|{}|{}", name, HASH, HASH)
+    }
+
+    fn standard() -> Value {
+        json!({"dir_name": "root", "files": [link("a")], "dirs": [
+            {"dir_name": "sub", "files": [link("b")], "dirs": []}
+        ]})
+    }
+
+    fn flat() -> Value {
+        json!([
+            {"name": "a", "size": 1, "sha1": HASH, "preid": HASH, "path": "root"},
+            {"name": "b", "size": "1", "sha1": HASH, "preid": HASH, "path": "other/b"},
+        ])
+    }
+
+    fn entity_array() -> Value {
+        json!([
+            {"dir_name": "root", "files": [link("a")], "dirs": []},
+            {"dir_name": "other", "files": [link("b")], "dirs": []},
+        ])
+    }
+
+    fn children_tree() -> Value {
+        json!({"name": "root", "children": [
+            {"name": "a", "size": 1, "sha1": HASH, "preid": HASH},
+            {"name": "empty"},
+        ]})
+    }
+
+    fn parse(value: &Value) -> (Sha1Entity, JsonFormat) {
+        parse_sha1_json(value.to_string().as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn detects_every_shape() {
+        assert_eq!(detect_format(&standard()), Some(JsonFormat::Standard));
+        assert_eq!(detect_format(&flat()), Some(JsonFormat::Flat));
+        assert_eq!(
+            detect_format(&entity_array()),
+            Some(JsonFormat::EntityArray)
+        );
+        assert_eq!(
+            detect_format(&children_tree()),
+            Some(JsonFormat::ChildrenTree)
+        );
+        assert_eq!(detect_format(&json!([])), None);
+        assert_eq!(detect_format(&json!({"name": "a"})), None);
+    }
+
+    #[test]
+    fn parses_every_shape() {
+        let (entity, _) = parse(&standard());
+        assert!(!entity.synthetic);
+        assert_eq!(entity.dirs[0].files[0].name(), "b");
+
+        let (entity, _) = parse(&flat());
+        assert!(entity.synthetic);
+        let names: Vec<_> = entity.dirs.iter().map(|d| d.dir_name.as_str()).collect();
+        assert_eq!(names, ["root", "other"]);
+        assert_eq!(entity.dirs[1].files[0].name(), "b");
+
+        let (entity, _) = parse(&entity_array());
+        assert!(entity.synthetic);
+        assert_eq!(entity.dirs.len(), 2);
+
+        let (entity, _) = parse(&children_tree());
+        assert_eq!(entity.dir_name, "root");
+        assert_eq!(entity.files[0].size(), 1);
+        assert_eq!(entity.dirs[0].dir_name, "empty");
+    }
+
+    #[test]
+    fn a_folder_named_like_the_synthetic_root_is_kept() {
+        let value = json!({"dir_name": SYNTHETIC_ROOT, "files": [], "dirs": [
+            {"dir_name": "a", "files": [link("a")], "dirs": []},
+            {"dir_name": "b", "files": [link("b")], "dirs": []},
+        ]});
+        let (entity, _) = parse(&value);
+        assert_eq!(top_level(&entity).0.len(), 1);
+        let exported = export_sha1_json(&entity, JsonFormat::EntityArray).unwrap();
+        let roots: Vec<Sha1Entity> = serde_json::from_slice(&exported).unwrap();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].dir_name, SYNTHETIC_ROOT);
+    }
+
+    #[test]
+    fn several_roots_export_without_the_synthetic_root() {
+        let (entity, _) = parse(&entity_array());
+        let exported = export_sha1_json(&entity, JsonFormat::ChildrenTree).unwrap();
+        let (back, format) = parse_sha1_json(&exported).unwrap();
+        assert_eq!(format, JsonFormat::ChildrenTree);
+        assert!(back.synthetic);
+        assert_eq!(back.dirs.len(), 2);
+    }
+}
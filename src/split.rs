@@ -0,0 +1,205 @@
+///
+/// splitting a list into parts small enough for the 115 import tools
+///
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::line_parser::Sha1Line;
+use crate::list_ops::{list_bytes, list_extension, load_list};
+use crate::parsers::{parse_size, to_iec, write_all_to_file};
+
+/// more parts than this are sent as a single zip
+pub(crate) const ZIP_THRESHOLD: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SplitMode {
+    /// at most this many links per part
+    Count(usize),
+    /// at most this many bytes per part, a larger file gets a part of its own
+    Size(u64),
+    /// one part per folder below the path all files share
+    TopFolder,
+}
+
+impl FromStr for SplitMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mode = match s {
+            "folder" | "目录" => SplitMode::TopFolder,
+            _ if s.chars().all(|c| c.is_ascii_digit()) => SplitMode::Count(s.parse()?),
+            _ => SplitMode::Size(parse_size(s).ok_or_else(|| anyhow!("invalid size: {}", s))?),
+        };
+        if mode == SplitMode::Count(0) || mode == SplitMode::Size(0) {
+            bail!("part limit can't be zero");
+        }
+        Ok(mode)
+    }
+}
+
+impl fmt::Display for SplitMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitMode::Count(n) => write!(f, "每份最多 {} 个链接", n),
+            SplitMode::Size(size) => write!(f, "每份最多 {}", to_iec(*size)),
+            SplitMode::TopFolder => write!(f, "按目录"),
+        }
+    }
+}
+
+pub(crate) struct SplitPart {
+    pub(crate) path: PathBuf,
+    files: usize,
+    size: u64,
+}
+
+pub(crate) struct SplitReport {
+    mode: SplitMode,
+    pub(crate) parts: Vec<SplitPart>,
+}
+
+impl fmt::Display for SplitReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, 共拆分成 {} 份", self.mode, self.parts.len())?;
+        for part in self.parts.iter().take(10) {
+            let name = part.path.file_name().unwrap_or_default().to_string_lossy();
+            write!(
+                f,
+                "\n{}: {} 个文件, {}",
+                name,
+                part.files,
+                to_iec(part.size)
+            )?;
+        }
+        if self.parts.len() > 10 {
+            write!(f, "\n...")?;
+        }
+        Ok(())
+    }
+}
+
+fn common_prefix_len(records: &[Sha1Line]) -> usize {
+    let first = match records.first() {
+        Some(record) => &record.folders,
+        None => return 0,
+    };
+    records.iter().fold(first.len(), |len, record| {
+        first
+            .iter()
+            .zip(&record.folders)
+            .take(len)
+            .take_while(|(a, b)| a == b)
+            .count()
+    })
+}
+
+/// the groups in the order they first show up, named after their folder in folder mode
+fn group(mode: SplitMode, records: Vec<Sha1Line>) -> Vec<(Option<String>, Vec<Sha1Line>)> {
+    let mut groups: Vec<(Option<String>, Vec<Sha1Line>)> = Vec::new();
+    match mode {
+        SplitMode::Count(max) => {
+            for record in records {
+                match groups.last_mut() {
+                    Some((_, part)) if part.len() < max => part.push(record),
+                    _ => groups.push((None, vec![record])),
+                }
+            }
+        }
+        SplitMode::Size(max) => {
+            let mut part_size = 0;
+            for record in records {
+                let size = record.file.size();
+                match groups.last_mut() {
+                    Some((_, part)) if part_size + size <= max => {
+                        part.push(record);
+                        part_size += size;
+                    }
+                    _ => {
+                        groups.push((None, vec![record]));
+                        part_size = size;
+                    }
+                }
+            }
+        }
+        SplitMode::TopFolder => {
+            let depth = common_prefix_len(&records);
+            let mut index: HashMap<String, usize> = HashMap::new();
+            for record in records {
+                // files right in the shared folder are named after it
+                let name = record
+                    .folders
+                    .get(depth)
+                    .or_else(|| record.folders.last())
+                    .cloned()
+                    .unwrap_or_else(|| "根目录".to_owned());
+                match index.get(&name) {
+                    Some(i) => groups[*i].1.push(record),
+                    None => {
+                        index.insert(name.to_owned(), groups.len());
+                        groups.push((Some(name), vec![record]));
+                    }
+                }
+            }
+        }
+    }
+    groups
+}
+
+fn safe_filename(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// split a txt or json list into numbered parts in `out_dir`, json parts keep the input shape
+pub(crate) async fn split_list(
+    input: &Path,
+    mode: SplitMode,
+    out_dir: &Path,
+    stem: &str,
+) -> Result<SplitReport> {
+    let (records, format) = load_list(input).await?;
+    if records.is_empty() {
+        bail!("nothing to split in {}", input.to_string_lossy());
+    }
+
+    let groups = group(mode, records);
+    let width = groups.len().to_string().len().max(2);
+    let mut parts = Vec::new();
+    for (i, (name, records)) in groups.into_iter().enumerate() {
+        let mut filename = format!("{}_part{:0width$}", stem, i + 1, width = width);
+        if let Some(name) = name {
+            filename = format!("{}_{}", filename, safe_filename(&name));
+        }
+        let files = records.len();
+        let size = records.iter().map(|r| r.file.size()).sum();
+
+        let path = out_dir.join(format!("{}.{}", filename, list_extension(format)));
+        write_all_to_file(&path, &list_bytes(records, format)?).await?;
+        parts.push(SplitPart { path, files, size });
+    }
+
+    Ok(SplitReport { mode, parts })
+}
+
+/// every part in one zip, flat
+pub(crate) fn zip_parts(parts: &[SplitPart], output: &Path) -> Result<()> {
+    let file = std::fs::File::create(output)?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for part in parts {
+        let name = part.path.file_name().unwrap_or_default().to_string_lossy();
+        zip.start_file(name.to_string(), options)?;
+        zip.write_all(&std::fs::read(&part.path)?)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
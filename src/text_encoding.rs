@@ -0,0 +1,225 @@
+///
+/// telling which encoding a text file uses and turning it into utf8
+///
+/// byte order marks are handled in `io`, files are read through `io::read_utf8` and `io::open_utf8`
+///
+use anyhow::{Context, Result};
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, GB18030, GBK, UTF_16BE, UTF_16LE, UTF_8};
+use std::borrow::Cow;
+use std::fmt;
+use std::io::SeekFrom;
+use std::path::Path;
+use tokio::fs::File as TokioFile;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::io::{read_sample, Bom};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TextEncoding {
+    /// told by a byte order mark
+    Marked(Bom),
+    Guessed(&'static Encoding),
+}
+
+impl TextEncoding {
+    pub(crate) fn is_plain_utf8(&self) -> bool {
+        *self == TextEncoding::Guessed(UTF_8)
+    }
+
+    /// utf8 with or without a bom
+    pub(crate) fn is_utf8(&self) -> bool {
+        *self == TextEncoding::Marked(Bom::Utf8) || self.is_plain_utf8()
+    }
+
+    pub(crate) fn bom_len(&self) -> usize {
+        match self {
+            TextEncoding::Marked(bom) => bom.len(),
+            TextEncoding::Guessed(_) => 0,
+        }
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextEncoding::Marked(bom) => write!(f, "{} (带 BOM)", bom),
+            // the gbk decoder reads all of gb18030
+            TextEncoding::Guessed(encoding) if *encoding == GBK => write!(f, "GBK/GB18030"),
+            TextEncoding::Guessed(encoding) => write!(f, "{}", encoding.name()),
+        }
+    }
+}
+
+/// utf16 without a bom, told apart by where the zero bytes of ascii text fall
+fn guess_utf16(sample: &[u8]) -> Option<&'static Encoding> {
+    let pairs = sample.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+    let even = sample.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|b| **b == 0)
+        .count();
+    if odd * 3 > pairs && even * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even * 3 > pairs && odd * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// `truncated` when the sample is cut from a longer file and may end inside a character
+pub(crate) fn detect_sample(sample: &[u8], truncated: bool) -> TextEncoding {
+    if let Some(bom) = Bom::sniff(sample) {
+        return TextEncoding::Marked(bom);
+    }
+    if let Some(encoding) = guess_utf16(sample) {
+        return TextEncoding::Guessed(encoding);
+    }
+    let is_utf8 = match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        Err(e) => truncated && e.error_len().is_none(),
+    };
+    if is_utf8 {
+        return TextEncoding::Guessed(UTF_8);
+    }
+
+    let mut detector = EncodingDetector::new();
+    detector.feed(sample, !truncated);
+    let encoding = detector.guess(None, true);
+    if encoding == GB18030 {
+        TextEncoding::Guessed(GBK)
+    } else {
+        TextEncoding::Guessed(encoding)
+    }
+}
+
+pub(crate) fn detect(bytes: &[u8]) -> TextEncoding {
+    detect_sample(bytes, false)
+}
+
+/// the text without its bom, borrowed when it already is utf8
+pub(crate) fn decode(bytes: &[u8]) -> (Cow<'_, str>, TextEncoding) {
+    let detected = detect(bytes);
+    let text = match detected {
+        TextEncoding::Marked(bom) => bom.decode(&bytes[bom.len()..]),
+        TextEncoding::Guessed(encoding) => encoding.decode_without_bom_handling(bytes).0,
+    };
+    (text, detected)
+}
+
+/// whether the bytes after a sample that was valid utf8 still are, `file` is past the sample
+async fn rest_is_utf8(file: &mut TokioFile, sample: &[u8]) -> Result<bool> {
+    // the sample may end inside a character
+    let valid = match std::str::from_utf8(sample) {
+        Ok(_) => sample.len(),
+        Err(e) => e.valid_up_to(),
+    };
+    let mut pending = sample[valid..].to_vec();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = file
+            .read(&mut buf)
+            .await
+            .context("fail to read bytes from file")?;
+        if read == 0 {
+            return Ok(pending.is_empty());
+        }
+        pending.extend_from_slice(&buf[..read]);
+        match std::str::from_utf8(&pending) {
+            Ok(_) => pending.clear(),
+            Err(e) if e.error_len().is_none() => {
+                pending.drain(..e.valid_up_to());
+            }
+            Err(_) => return Ok(false),
+        }
+    }
+}
+
+/// the encoding of the whole file, a head that looks like utf8 is checked against the rest, so a
+/// gbk file starting with ascii lines is still found, `file` is left anywhere
+pub(crate) async fn detect_file(file: &mut TokioFile) -> Result<TextEncoding> {
+    let (sample, truncated) = read_sample(file).await?;
+    let detected = detect_sample(&sample, truncated);
+    if !truncated || !detected.is_plain_utf8() {
+        return Ok(detected);
+    }
+    file.seek(SeekFrom::Start(sample.len() as u64)).await?;
+    if rest_is_utf8(file, &sample).await? {
+        return Ok(detected);
+    }
+    file.seek(SeekFrom::Start(0)).await?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)
+        .await
+        .context("fail to read bytes from file")?;
+    Ok(detect(&bytes))
+}
+
+pub(crate) async fn file_encoding(input: &Path) -> Result<TextEncoding> {
+    let mut file = TokioFile::open(input)
+        .await
+        .context(format!("failed to open {}", input.to_string_lossy()))?;
+    detect_file(&mut file).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncBufReadExt;
+
+    const TEXT: &str = "115://中文名字.mkv|1|0123456789ABCDEF0123456789ABCDEF01234567|0123456789ABCDEF0123456789ABCDEF01234567|电影\n";
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn detects_gbk() {
+        let bytes = GBK.encode(&TEXT.repeat(20)).0.into_owned();
+        assert_eq!(detect(&bytes), TextEncoding::Guessed(GBK));
+        assert_eq!(decode(&bytes).0, TEXT.repeat(20));
+    }
+
+    #[test]
+    fn detects_utf16_with_and_without_bom() {
+        let plain = utf16le(TEXT);
+        assert_eq!(detect(&plain), TextEncoding::Guessed(UTF_16LE));
+        assert_eq!(decode(&plain).0, TEXT);
+        let marked = [&[0xff, 0xfe][..], &plain].concat();
+        assert_eq!(detect(&marked), TextEncoding::Marked(Bom::Utf16Le));
+        assert_eq!(decode(&marked).0, TEXT);
+    }
+
+    #[test]
+    fn detects_utf8_and_strips_its_bom() {
+        assert!(detect(TEXT.as_bytes()).is_plain_utf8());
+        let marked = [&[0xef, 0xbb, 0xbf][..], TEXT.as_bytes()].concat();
+        assert_eq!(detect(&marked), TextEncoding::Marked(Bom::Utf8));
+        assert_eq!(decode(&marked).0, TEXT);
+    }
+
+    #[tokio::test]
+    async fn looks_past_an_ascii_head() {
+        let head = "115://a.mkv|1|0123456789ABCDEF0123456789ABCDEF01234567|0123456789ABCDEF0123456789ABCDEF01234567|dir\n";
+        let text = head.repeat(1000) + &TEXT.repeat(20);
+        let path = std::env::temp_dir().join(format!("ascii_head_{}.txt", std::process::id()));
+        std::fs::write(&path, GBK.encode(&text).0).unwrap();
+
+        let detected = file_encoding(&path).await.unwrap();
+        let mut lines = crate::io::open_utf8(&path).await.unwrap().lines();
+        let mut read = String::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            read.push_str(&line);
+            read.push('\n');
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(detected, TextEncoding::Guessed(GBK));
+        assert_eq!(read, text);
+    }
+}
@@ -0,0 +1,548 @@
+///
+/// native .torrent inspection, no more transmission-show / aria2c
+///
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{TimeZone, Utc};
+use crypto::{digest::Digest, sha1::Sha1, sha2::Sha256};
+use serde::{Deserialize, Serialize};
+use serde_bencode::value::Value;
+use serde_bytes::ByteBuf;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use tokio::io::AsyncReadExt;
+
+use crate::io::check_input;
+use crate::parsers::to_iec;
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+struct Node(String, i64);
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct File {
+    pub(crate) path: Vec<String>,
+    pub(crate) length: i64,
+    #[serde(default)]
+    md5sum: Option<String>,
+    /// "p" marks the padding files of hybrid torrents
+    #[serde(default)]
+    attr: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct Info {
+    pub(crate) name: String,
+    /// absent in v2 only torrents
+    #[serde(default)]
+    pieces: Option<ByteBuf>,
+    #[serde(rename = "piece length")]
+    pub(crate) piece_length: i64,
+    #[serde(default)]
+    md5sum: Option<String>,
+    #[serde(default)]
+    pub(crate) length: Option<i64>,
+    #[serde(default)]
+    pub(crate) files: Option<Vec<File>>,
+    #[serde(default)]
+    pub(crate) private: Option<u8>,
+    #[serde(default)]
+    path: Option<Vec<String>>,
+    #[serde(default)]
+    #[serde(rename = "root hash")]
+    root_hash: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "meta version")]
+    meta_version: Option<u8>,
+    /// v2 file tree, `{name: {name: {"": {length, pieces root}}}}`
+    #[serde(default)]
+    #[serde(rename = "file tree")]
+    file_tree: Option<Value>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct Torrent {
+    pub(crate) info: Info,
+    #[serde(default, skip)]
+    announce: Option<String>,
+    #[serde(default, skip)]
+    nodes: Option<Vec<Node>>,
+    #[serde(default, skip)]
+    encoding: Option<String>,
+    #[serde(default, skip)]
+    httpseeds: Option<Vec<String>>,
+    #[serde(default, skip)]
+    #[serde(rename = "announce-list")]
+    announce_list: Option<Vec<Vec<String>>>,
+    #[serde(default)]
+    #[serde(rename = "creation date")]
+    pub(crate) creation_date: Option<i64>,
+    #[serde(default)]
+    #[serde(rename = "comment")]
+    pub(crate) comment: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "created by")]
+    pub(crate) created_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TorrentVersion {
+    V1,
+    V2,
+    Hybrid,
+}
+
+impl fmt::Display for TorrentVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TorrentVersion::V1 => write!(f, "v1"),
+            TorrentVersion::V2 => write!(f, "v2"),
+            TorrentVersion::Hybrid => write!(f, "v1+v2 混合"),
+        }
+    }
+}
+
+/// a single file inside a torrent, path includes the torrent name for multi file torrents
+#[derive(Debug, Serialize)]
+pub(crate) struct TorrentEntry {
+    pub(crate) path: Vec<String>,
+    pub(crate) length: u64,
+}
+
+/// a decoded torrent together with its infohashes
+#[derive(Debug)]
+pub(crate) struct TorrentMeta {
+    pub(crate) torrent: Torrent,
+    pub(crate) version: TorrentVersion,
+    /// v1 infohash (sha1), lowercase hex
+    pub(crate) info_hash: Option<String>,
+    /// v2 infohash (sha256), lowercase hex
+    pub(crate) info_hash_v2: Option<String>,
+}
+
+impl TorrentMeta {
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let torrent: Torrent =
+            serde_bencode::from_bytes(bytes).context("failed to decode torrent")?;
+        let info = &torrent.info;
+        let version = match (info.meta_version, &info.pieces) {
+            (Some(2), Some(_)) => TorrentVersion::Hybrid,
+            (Some(2), None) => TorrentVersion::V2,
+            (_, Some(_)) => TorrentVersion::V1,
+            (_, None) => bail!("torrent has neither pieces nor a v2 file tree"),
+        };
+        if version != TorrentVersion::V1 && info.file_tree.is_none() {
+            bail!("v2 torrent without file tree");
+        }
+
+        let raw = raw_info(bytes)?;
+        let info_hash = (version != TorrentVersion::V2).then(|| sha1(raw));
+        let info_hash_v2 = (version != TorrentVersion::V1).then(|| sha256(raw));
+        Ok(Self {
+            torrent,
+            version,
+            info_hash,
+            info_hash_v2,
+        })
+    }
+
+    pub(crate) async fn from_path(path: &Path) -> Result<Self> {
+        check_input(path).await?;
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .context(format!("failed to open {}", path.to_string_lossy()))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).await?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.torrent.info.name
+    }
+
+    /// every real file of the torrent, padding files are left out
+    pub(crate) fn files(&self) -> Vec<TorrentEntry> {
+        let info = &self.torrent.info;
+        if self.version == TorrentVersion::V2 {
+            let mut list = Vec::new();
+            if let Some(tree) = &info.file_tree {
+                walk_file_tree(tree, &mut Vec::new(), &mut list);
+            }
+            // a single file v2 torrent keeps its only file at the top of the tree
+            let single = list.len() == 1 && list[0].path == [info.name.as_str()];
+            if !single {
+                list.iter_mut()
+                    .for_each(|entry| entry.path.insert(0, info.name.to_owned()));
+            }
+            return list;
+        }
+
+        match &info.files {
+            Some(files) => files
+                .iter()
+                .filter(|f| !f.attr.as_deref().unwrap_or("").contains('p'))
+                .map(|f| TorrentEntry {
+                    path: std::iter::once(info.name.to_owned())
+                        .chain(f.path.iter().cloned())
+                        .collect(),
+                    length: f.length.max(0) as u64,
+                })
+                .collect(),
+            None => vec![TorrentEntry {
+                path: vec![info.name.to_owned()],
+                length: info.length.unwrap_or(0).max(0) as u64,
+            }],
+        }
+    }
+
+    pub(crate) fn total_length(&self) -> u64 {
+        self.files().iter().map(|f| f.length).sum()
+    }
+
+    pub(crate) fn file_count(&self) -> usize {
+        self.files().len()
+    }
+
+    pub(crate) fn magnet(&self) -> String {
+        let mut xt = Vec::new();
+        if let Some(hash) = &self.info_hash {
+            xt.push(format!("xt=urn:btih:{}", hash));
+        }
+        if let Some(hash) = &self.info_hash_v2 {
+            xt.push(format!("xt=urn:btmh:{}", sha256_multihash(hash)));
+        }
+        format!("magnet:?{}", xt.join("&"))
+    }
+
+    /// the `n` largest files, biggest first
+    pub(crate) fn largest_files(&self, n: usize) -> Vec<TorrentEntry> {
+        let mut files = self.files();
+        files.sort_by_key(|f| std::cmp::Reverse(f.length));
+        files.truncate(n);
+        files
+    }
+
+    /// an indented folder tree, at most `max_children` entries per folder and `max_depth` levels
+    pub(crate) fn folder_tree(&self, max_depth: usize, max_children: usize) -> String {
+        let mut root = TreeNode::default();
+        for entry in self.files() {
+            root.insert(&entry.path, entry.length);
+        }
+        let mut res = String::new();
+        // multi file torrents keep everything under one folder, start from there
+        if root.files.is_empty() && root.dirs.len() == 1 {
+            let (name, dir) = root.dirs.iter().next().unwrap();
+            res.push_str(&format!(
+                "📁 {} ({} 个文件, {})\n",
+                name,
+                dir.count,
+                to_iec(dir.size)
+            ));
+            dir.render(&mut res, "", max_depth, max_children);
+        } else {
+            root.render(&mut res, "", max_depth, max_children);
+        }
+        res
+    }
+
+    /// one `size<TAB>path` line per file
+    pub(crate) fn file_list_txt(&self) -> String {
+        self.files()
+            .iter()
+            .map(|f| format!("{}\t{}\n", f.length, f.path.join("/")))
+            .collect()
+    }
+
+    pub(crate) fn file_list_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.files())?)
+    }
+
+    /// piece length, private flag, creator and the like
+    pub(crate) fn details(&self) -> String {
+        let info = &self.torrent.info;
+        let mut res = format!(
+            "版本: {}\n分块大小: {}\n私有种子: {}",
+            self.version,
+            to_iec(info.piece_length.max(0) as u64),
+            if info.private == Some(1) {
+                "是"
+            } else {
+                "否"
+            },
+        );
+        if let Some(created_by) = &self.torrent.created_by {
+            res.push_str(&format!("\n制作工具: {}", created_by));
+        }
+        if let Some(date) = self
+            .torrent
+            .creation_date
+            .and_then(|ts| Utc.timestamp_opt(ts, 0).single())
+        {
+            res.push_str(&format!(
+                "\n创建时间: {}",
+                date.format("%Y-%m-%d %H:%M:%S UTC")
+            ));
+        }
+        if let Some(comment) = &self.torrent.comment {
+            res.push_str(&format!("\n备注: {}", comment));
+        }
+        if let Some(hash) = &self.info_hash {
+            res.push_str(&format!("\nv1 infohash: {}", hash));
+        }
+        if let Some(hash) = &self.info_hash_v2 {
+            res.push_str(&format!("\nv2 infohash: {}", hash));
+        }
+        res
+    }
+}
+
+#[derive(Default)]
+struct TreeNode {
+    dirs: BTreeMap<String, TreeNode>,
+    files: Vec<(String, u64)>,
+    size: u64,
+    count: usize,
+}
+
+impl TreeNode {
+    fn insert(&mut self, path: &[String], length: u64) {
+        self.size += length;
+        self.count += 1;
+        match path {
+            [] => {}
+            [name] => self.files.push((name.to_owned(), length)),
+            [dir, rest @ ..] => self
+                .dirs
+                .entry(dir.to_owned())
+                .or_default()
+                .insert(rest, length),
+        }
+    }
+
+    fn render(&self, res: &mut String, prefix: &str, depth: usize, max_children: usize) {
+        let total = self.dirs.len() + self.files.len();
+        let mut shown = 0;
+        for (name, dir) in &self.dirs {
+            if shown == max_children {
+                break;
+            }
+            shown += 1;
+            let last = shown == total;
+            res.push_str(&format!(
+                "{}{}📁 {} ({} 个文件, {})\n",
+                prefix,
+                if last { "└─ " } else { "├─ " },
+                name,
+                dir.count,
+                to_iec(dir.size),
+            ));
+            if depth > 1 {
+                let prefix = format!("{}{}", prefix, if last { "   " } else { "│  " });
+                dir.render(res, &prefix, depth - 1, max_children);
+            }
+        }
+        for (name, size) in &self.files {
+            if shown == max_children {
+                break;
+            }
+            shown += 1;
+            res.push_str(&format!(
+                "{}{}{} ({})\n",
+                prefix,
+                if shown == total { "└─ " } else { "├─ " },
+                name,
+                to_iec(*size),
+            ));
+        }
+        if shown < total {
+            res.push_str(&format!("{}└─ ... 其余 {} 项\n", prefix, total - shown));
+        }
+    }
+}
+
+/// multihash form of a sha256 digest, as used in `urn:btmh:`
+pub(crate) fn sha256_multihash(hash_hex: &str) -> String {
+    format!("1220{}", hash_hex)
+}
+
+fn walk_file_tree(node: &Value, path: &mut Vec<String>, list: &mut Vec<TorrentEntry>) {
+    let dict = match node {
+        Value::Dict(dict) => dict,
+        _ => return,
+    };
+    let mut keys: Vec<&Vec<u8>> = dict.keys().collect();
+    keys.sort();
+    for key in keys {
+        let child = &dict[key];
+        if key.is_empty() {
+            let length = match child {
+                Value::Dict(leaf) => match leaf.get(&b"length"[..]) {
+                    Some(Value::Int(length)) => (*length).max(0) as u64,
+                    _ => 0,
+                },
+                _ => 0,
+            };
+            list.push(TorrentEntry {
+                path: path.clone(),
+                length,
+            });
+        } else {
+            path.push(String::from_utf8_lossy(key).to_string());
+            walk_file_tree(child, path, list);
+            path.pop();
+        }
+    }
+}
+
+/// the raw bytes of the top level `info` dictionary, exactly as they appear in the file
+fn raw_info(bytes: &[u8]) -> Result<&[u8]> {
+    if bytes.first() != Some(&b'd') {
+        bail!("torrent is not a bencoded dictionary");
+    }
+    let mut pos = 1;
+    while bytes.get(pos) != Some(&b'e') {
+        let key_end = value_end(bytes, pos)?;
+        let key = string_content(&bytes[pos..key_end])?;
+        let end = value_end(bytes, key_end)?;
+        if key == b"info" {
+            return Ok(&bytes[key_end..end]);
+        }
+        pos = end;
+    }
+    bail!("info dictionary not found in torrent")
+}
+
+/// index right after the bencoded value starting at `start`
+fn value_end(bytes: &[u8], start: usize) -> Result<usize> {
+    let unexpected_eof = || anyhow!("unexpected end of bencode");
+    match *bytes.get(start).ok_or_else(unexpected_eof)? {
+        b'i' => {
+            let len = bytes[start..]
+                .iter()
+                .position(|&b| b == b'e')
+                .ok_or_else(unexpected_eof)?;
+            Ok(start + len + 1)
+        }
+        b'l' | b'd' => {
+            let mut pos = start + 1;
+            while *bytes.get(pos).ok_or_else(unexpected_eof)? != b'e' {
+                pos = value_end(bytes, pos)?;
+            }
+            Ok(pos + 1)
+        }
+        b'0'..=b'9' => {
+            let colon = bytes[start..]
+                .iter()
+                .position(|&b| b == b':')
+                .ok_or_else(unexpected_eof)?
+                + start;
+            let len: usize = std::str::from_utf8(&bytes[start..colon])?.parse()?;
+            let end = colon + 1 + len;
+            if end > bytes.len() {
+                return Err(unexpected_eof());
+            }
+            Ok(end)
+        }
+        other => bail!("invalid bencode token: {:?}", other as char),
+    }
+}
+
+fn string_content(raw: &[u8]) -> Result<&[u8]> {
+    let colon = raw
+        .iter()
+        .position(|&b| b == b':')
+        .ok_or_else(|| anyhow!("dictionary key is not a string"))?;
+    Ok(&raw[colon + 1..])
+}
+
+fn sha1(bytes: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.input(bytes);
+    hasher.result_str()
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(bytes);
+    hasher.result_str()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIECES: &str = "aaaaaaaaaaaaaaaaaaaa";
+    const FILE_TREE: &str =
+        "d5:a.txtd0:d6:lengthi5e11:pieces root32:bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbeee";
+
+    fn meta(torrent: &str) -> TorrentMeta {
+        TorrentMeta::from_bytes(torrent.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn v1_infohash() {
+        let torrent = format!(
+            "d8:announce4:http4:infod6:lengthi5e4:name5:a.txt12:piece lengthi16384e6:pieces20:{}ee",
+            PIECES
+        );
+        let meta = meta(&torrent);
+        assert_eq!(meta.version, TorrentVersion::V1);
+        assert_eq!(
+            meta.info_hash.as_deref(),
+            Some("7faf75b2447f88700c68f1eceda713cd90a0127a")
+        );
+        assert_eq!(meta.info_hash_v2, None);
+        assert_eq!(
+            meta.magnet(),
+            "magnet:?xt=urn:btih:7faf75b2447f88700c68f1eceda713cd90a0127a"
+        );
+        assert_eq!(meta.total_length(), 5);
+    }
+
+    #[test]
+    fn v2_infohash() {
+        let torrent = format!(
+            "d4:infod9:file tree{}12:meta versioni2e4:name5:a.txt12:piece lengthi16384eee",
+            FILE_TREE
+        );
+        let meta = meta(&torrent);
+        assert_eq!(meta.version, TorrentVersion::V2);
+        assert_eq!(meta.info_hash, None);
+        assert_eq!(
+            meta.info_hash_v2.as_deref(),
+            Some("d9c474b507ea61d1d2e509cab047a3d39c747b7db32213b0a5f48693ab529252")
+        );
+        assert_eq!(meta.file_count(), 1);
+    }
+
+    #[test]
+    fn hybrid_infohashes() {
+        let torrent = format!(
+            "d4:infod9:file tree{}6:lengthi5e12:meta versioni2e4:name5:a.txt12:piece lengthi16384e6:pieces20:{}ee",
+            FILE_TREE, PIECES
+        );
+        let meta = meta(&torrent);
+        assert_eq!(meta.version, TorrentVersion::Hybrid);
+        assert_eq!(
+            meta.info_hash.as_deref(),
+            Some("488f1cf2b01026edb6a4b6e666b68b7152d62a24")
+        );
+        assert_eq!(
+            meta.info_hash_v2.as_deref(),
+            Some("a20c6ed90295f448b2555129ea9e460d1c184c4e9b5b36de5a53f7e0a86fd991")
+        );
+        assert!(meta.magnet().contains(
+            "&xt=urn:btmh:1220a20c6ed90295f448b2555129ea9e460d1c184c4e9b5b36de5a53f7e0a86fd991"
+        ));
+    }
+
+    #[test]
+    fn rejects_broken_torrents() {
+        assert!(TorrentMeta::from_bytes(b"d4:infod4:name1:a12:piece lengthi1eee").is_err());
+        assert!(raw_info(b"d8:announce4:http").is_err());
+        assert!(raw_info(b"l4:infoe").is_err());
+    }
+}
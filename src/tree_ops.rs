@@ -0,0 +1,296 @@
+///
+/// reshaping the folder tree of a list before importing it
+///
+use anyhow::{anyhow, bail, Result};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::parsers::{FileRepr, Sha1Entity, TreeBuilder};
+use crate::sha1_json::wrap_roots;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SortKey {
+    Name,
+    /// largest first
+    Size,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TreeOp {
+    RenameRoot(String),
+    /// drop this many leading folder levels
+    Strip(usize),
+    /// every file directly in the root
+    Flatten,
+    GroupByExtension,
+    GroupByKind,
+    PruneEmpty,
+    Sort(SortKey),
+}
+
+impl FromStr for TreeOp {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (op, arg) = s.split_once(' ').unwrap_or((s, ""));
+        let arg = arg.trim();
+        Ok(match op {
+            "rename" | "改名" if !arg.is_empty() => TreeOp::RenameRoot(arg.to_owned()),
+            "strip" | "去层" => TreeOp::Strip(if arg.is_empty() { 1 } else { arg.parse()? }),
+            "flatten" | "展平" => TreeOp::Flatten,
+            "ext" | "按扩展名" => TreeOp::GroupByExtension,
+            "type" | "按类型" => TreeOp::GroupByKind,
+            "prune" | "去空目录" => TreeOp::PruneEmpty,
+            "sort" | "排序" => TreeOp::Sort(match arg {
+                "" | "name" | "名称" => SortKey::Name,
+                "size" | "大小" => SortKey::Size,
+                _ => bail!("unknown sort key: {}", arg),
+            }),
+            _ => bail!("unknown tree operation: {}", s),
+        })
+    }
+}
+
+impl fmt::Display for TreeOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeOp::RenameRoot(name) => write!(f, "根目录改名为 {}", name),
+            TreeOp::Strip(n) => write!(f, "去掉 {} 层目录", n),
+            TreeOp::Flatten => write!(f, "展平"),
+            TreeOp::GroupByExtension => write!(f, "按扩展名分组"),
+            TreeOp::GroupByKind => write!(f, "按类型分组"),
+            TreeOp::PruneEmpty => write!(f, "去掉空目录"),
+            TreeOp::Sort(SortKey::Name) => write!(f, "按名称排序"),
+            TreeOp::Sort(SortKey::Size) => write!(f, "按大小排序"),
+        }
+    }
+}
+
+/// `strip 1, prune, sort size`
+pub(crate) fn parse_tree_ops(s: &str) -> Result<Vec<TreeOp>> {
+    let ops = s
+        .split([',', '，'])
+        .filter(|op| !op.trim().is_empty())
+        .map(TreeOp::from_str)
+        .collect::<Result<Vec<_>>>()?;
+    if ops.is_empty() {
+        bail!("no tree operation");
+    }
+    Ok(ops)
+}
+
+/// the real top level folders, the synthetic parent does not count as a level
+fn into_top_level(entity: Sha1Entity) -> (Vec<Sha1Entity>, Vec<FileRepr>) {
+    if entity.synthetic {
+        (entity.dirs, entity.files)
+    } else {
+        (vec![entity], Vec::new())
+    }
+}
+
+/// files and empty folders go into the tree without the first `levels` folders of their path,
+/// files above that end up at the top, folders with the same name end up as one
+fn strip_into(tree: &mut TreeBuilder, entity: Sha1Entity, path: &mut Vec<String>, levels: usize) {
+    path.push(entity.dir_name);
+    let kept = &path[levels.min(path.len())..];
+    if entity.files.is_empty() && entity.dirs.is_empty() && !kept.is_empty() {
+        tree.push_folder(kept, entity.line_number);
+    }
+    for file in entity.files {
+        tree.push_file(kept, file);
+    }
+    for dir in entity.dirs {
+        strip_into(tree, dir, path, levels);
+    }
+    path.pop();
+}
+
+fn take_all_files(entity: Sha1Entity, files: &mut Vec<FileRepr>) {
+    files.extend(entity.files);
+    for dir in entity.dirs {
+        take_all_files(dir, files);
+    }
+}
+
+fn total_size(entity: &Sha1Entity) -> u64 {
+    entity.files.iter().map(|f| f.size()).sum::<u64>()
+        + entity.dirs.iter().map(total_size).sum::<u64>()
+}
+
+fn group_files(entity: Sha1Entity, folder_of: impl Fn(&FileRepr) -> String) -> Sha1Entity {
+    let mut files = Vec::new();
+    let name = entity.dir_name.to_owned();
+    let synthetic = entity.synthetic;
+    take_all_files(entity, &mut files);
+
+    let mut groups: BTreeMap<String, Vec<FileRepr>> = BTreeMap::new();
+    for file in files {
+        groups.entry(folder_of(&file)).or_default().push(file);
+    }
+
+    let mut root = Sha1Entity::new(name);
+    root.synthetic = synthetic;
+    for (folder, files) in groups {
+        let mut dir = Sha1Entity::new(folder);
+        dir.files = files;
+        root.dirs.push(dir);
+    }
+    root
+}
+
+fn prune(entity: &mut Sha1Entity) {
+    for dir in entity.dirs.iter_mut() {
+        prune(dir);
+    }
+    entity
+        .dirs
+        .retain(|dir| !dir.files.is_empty() || !dir.dirs.is_empty());
+}
+
+/// the order the lines had in the list is forgotten, the new one is what gets written
+fn sort(entity: &mut Sha1Entity, key: SortKey) {
+    for dir in entity.dirs.iter_mut() {
+        sort(dir, key);
+    }
+    entity.line_number = None;
+    for file in entity.files.iter_mut() {
+        file.set_line_number(None);
+    }
+    match key {
+        SortKey::Name => {
+            entity.files.sort_by_cached_key(|f| f.name().to_lowercase());
+            entity
+                .dirs
+                .sort_by_cached_key(|d| d.dir_name.to_lowercase());
+        }
+        SortKey::Size => {
+            entity.files.sort_by_key(|f| Reverse(f.size()));
+            entity.dirs.sort_by_cached_key(|d| Reverse(total_size(d)));
+        }
+    }
+}
+
+impl TreeOp {
+    pub(crate) fn apply(&self, mut entity: Sha1Entity) -> Result<Sha1Entity> {
+        match self {
+            TreeOp::RenameRoot(name) => {
+                entity.dir_name = name.to_owned();
+                entity.synthetic = false;
+                Ok(entity)
+            }
+            TreeOp::Strip(levels) => {
+                let (roots, loose) = into_top_level(entity);
+                let mut tree = TreeBuilder::default();
+                for file in loose {
+                    tree.push_file(&[] as &[String], file);
+                }
+                for root in roots {
+                    strip_into(&mut tree, root, &mut Vec::new(), *levels);
+                }
+                let (roots, loose) = tree.into_roots();
+                wrap_roots(roots, loose)
+                    .map_err(|_| anyhow!("nothing left after stripping {} levels", levels))
+            }
+            TreeOp::Flatten => {
+                let mut root = Sha1Entity::new(entity.dir_name.to_owned());
+                root.synthetic = entity.synthetic;
+                take_all_files(entity, &mut root.files);
+                Ok(root)
+            }
+            TreeOp::GroupByExtension => Ok(group_files(entity, |file| {
+                let ext = file.extension();
+                if ext.is_empty() {
+                    "无扩展名".to_owned()
+                } else {
+                    ext
+                }
+            })),
+            TreeOp::GroupByKind => Ok(group_files(entity, |file| file.kind().to_string())),
+            TreeOp::PruneEmpty => {
+                prune(&mut entity);
+                Ok(entity)
+            }
+            TreeOp::Sort(key) => {
+                sort(&mut entity, *key);
+                Ok(entity)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_parser::parse_lines;
+    use crate::parsers::entity_from_lines;
+
+    fn tree(paths: &[&str]) -> Sha1Entity {
+        let content: String = paths
+            .iter()
+            .enumerate()
+            .map(|(i, path)| format!("115://{}|1|{:040X}|{:040X}|{}\n", i, i, i, path))
+            .collect();
+        entity_from_lines(parse_lines(&content).flatten()).unwrap()
+    }
+
+    fn files(entity: &Sha1Entity) -> Vec<&str> {
+        entity.files.iter().map(|f| f.name()).collect()
+    }
+
+    #[test]
+    fn strip_merges_folders_with_the_same_name() {
+        let entity = tree(&["root|a|x", "root|b|x", "root|a", "root|b|y"]);
+        let stripped = TreeOp::Strip(2).apply(entity).unwrap();
+        assert!(stripped.synthetic);
+        assert_eq!(files(&stripped), ["2"]);
+        let names: Vec<_> = stripped.dirs.iter().map(|d| d.dir_name.as_str()).collect();
+        assert_eq!(names, ["x", "y"]);
+        assert_eq!(files(&stripped.dirs[0]), ["0", "1"]);
+    }
+
+    #[test]
+    fn strip_keeps_empty_folders_below_the_cut() {
+        let mut entity = tree(&["root|a|x"]);
+        entity.dirs[0]
+            .dirs
+            .push(Sha1Entity::new("empty".to_owned()));
+        let stripped = TreeOp::Strip(1).apply(entity).unwrap();
+        assert_eq!(stripped.dir_name, "a");
+        assert_eq!(stripped.dirs[1].dir_name, "empty");
+        let all_loose = TreeOp::Strip(3).apply(tree(&["root|a"])).unwrap();
+        assert!(all_loose.dirs.is_empty());
+        assert_eq!(files(&all_loose), ["0"]);
+    }
+
+    #[test]
+    fn loose_files_stay_through_the_ops() {
+        let entity = tree(&["root|a", ""]);
+        assert!(entity.synthetic);
+        assert_eq!(files(&entity), ["1"]);
+        let stripped = TreeOp::Strip(1).apply(entity).unwrap();
+        assert_eq!(files(&stripped), ["1"]);
+        let sorted = TreeOp::Sort(SortKey::Name).apply(stripped).unwrap();
+        assert_eq!(files(&sorted), ["1"]);
+        assert_eq!(sorted.dirs[0].dir_name, "a");
+        assert_eq!(files(&sorted.dirs[0]), ["0"]);
+        let flat = TreeOp::Flatten.apply(tree(&["root|a", ""])).unwrap();
+        assert_eq!(files(&flat), ["1", "0"]);
+    }
+
+    #[test]
+    fn parses_tree_ops() {
+        let ops = parse_tree_ops("strip 1， prune, sort size").unwrap();
+        assert_eq!(
+            ops,
+            [
+                TreeOp::Strip(1),
+                TreeOp::PruneEmpty,
+                TreeOp::Sort(SortKey::Size)
+            ]
+        );
+        assert!(parse_tree_ops(" , ").is_err());
+    }
+}